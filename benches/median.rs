@@ -0,0 +1,37 @@
+//! Times the sliding-window median against the original sort-every-window median; `tests/median.rs`
+//! checks they agree.
+//! Run with `cargo bench --bench median`.
+#![allow(clippy::needless_return)]
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::time::{Duration, Instant};
+use almostdefault::{bench::{median_filtered, median_filtered_reference}, upscale, Options, TextureKind};
+use common::noise_texture;
+use image::imageops::{self, FilterType};
+
+fn main() {
+    for (scale, window) in [(4, 3), (8, 5), (16, 9), (32, 17)] {
+        // A noisy 16x16 texture, pixel-doubled like median_upscale does before filtering
+        let texture = noise_texture(16, 16, scale);
+        let img = imageops::resize(&texture, 16 * scale, 16 * scale, FilterType::Nearest);
+
+        let (_fast, fast_time) = time(|| median_filtered(&img, window, false));
+        let (_reference, reference_time) = time(|| median_filtered_reference(&img, window, false));
+
+        println!("{scale:>2}x, {window:>2}x{window:<2} window: sliding {fast_time:>10.2?}, reference {reference_time:>10.2?}, {0:.1}x faster",
+            reference_time.as_secs_f64() / fast_time.as_secs_f64());
+    }
+
+    let options = Options::builder().scale(16).build().unwrap();
+    let texture = noise_texture(16, 16, 1);
+    let (_upscaled, upscale_time) = time(|| upscale(&texture, &options, TextureKind::Block).unwrap());
+    println!("16x block upscale of a 16x16 texture: {upscale_time:.2?}");
+}
+
+fn time<T>(f: impl Fn() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    return (result, start.elapsed());
+}
@@ -0,0 +1,163 @@
+use std::path::{Component, Path, PathBuf};
+
+/// The folder a texture sits in under `assets/<namespace>/textures/`, which says what the game uses it for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TextureCategory {
+    Block,
+    Item,
+    Entity,
+    Gui,
+    Particle,
+    Painting,
+    MobEffect,
+    Environment,
+    Font,
+    Map,
+    Misc,
+    Models,
+    Colormap,
+    Trims,
+    Effect,
+    /// A folder the game doesn't define, e.g. one added by a mod
+    Other,
+}
+
+impl TextureCategory {
+    fn from_folder(folder: &str) -> TextureCategory {
+        match folder {
+            // `blocks` and `items` are the folder names from before 1.13
+            "block" | "blocks" => return TextureCategory::Block,
+            "item" | "items" => return TextureCategory::Item,
+            "entity" => return TextureCategory::Entity,
+            "gui" => return TextureCategory::Gui,
+            "particle" => return TextureCategory::Particle,
+            "painting" => return TextureCategory::Painting,
+            "mob_effect" => return TextureCategory::MobEffect,
+            "environment" => return TextureCategory::Environment,
+            "font" => return TextureCategory::Font,
+            "map" => return TextureCategory::Map,
+            "misc" => return TextureCategory::Misc,
+            "models" => return TextureCategory::Models,
+            "colormap" => return TextureCategory::Colormap,
+            "trims" => return TextureCategory::Trims,
+            "effect" => return TextureCategory::Effect,
+            _ => return TextureCategory::Other
+        }
+    }
+}
+
+/// Where a texture sits in a pack: `assets/<namespace>/textures/<category>/<name>`. Any namespace is
+/// accepted, so mod textures are classified the same way as vanilla ones.
+#[derive(Debug)]
+pub(crate) struct TexturePath {
+    pub(crate) namespace: String,
+    pub(crate) category: TextureCategory,
+    /// The rest of the path below the category folder, e.g. `color_palettes/gold.png` for a trim
+    pub(crate) name: PathBuf,
+}
+
+/// Splits a resource path into its category and name, or `None` if it isn't under `assets/<namespace>/textures/<category>/`.
+pub(crate) fn parse_texture_path(resource: &Path) -> Option<TexturePath> {
+    let mut components = resource.components().map(|c| match c {
+        Component::Normal(n) => n.to_str(),
+        _ => None
+    });
+
+    if components.next()?? != "assets" {
+        return None;
+    }
+    let namespace = components.next()??.to_owned();
+    if components.next()?? != "textures" {
+        return None;
+    }
+    let category = TextureCategory::from_folder(components.next()??);
+
+    let mut name = PathBuf::new();
+    for c in components {
+        name.push(c?);
+    }
+    if name.as_os_str().is_empty() {
+        return None;
+    }
+
+    return Some(TexturePath {
+        namespace,
+        category,
+        name,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{parse_texture_path, TextureCategory};
+    use crate::{config::Config, resource_operations::{determine_resource_type, ResourceType}};
+
+    fn category(resource: &str) -> Option<TextureCategory> {
+        return parse_texture_path(Path::new(resource)).map(|t| t.category);
+    }
+
+    fn resource_type(resource: &str) -> ResourceType {
+        let resources = vec![PathBuf::from(resource)];
+        return determine_resource_type(&resources, &Config::default())[&resources[0]];
+    }
+
+    #[test]
+    fn category_comes_from_the_folder_not_the_file_name() {
+        assert_eq!(category("assets/minecraft/textures/block/item_frame.png"), Some(TextureCategory::Block));
+        assert_eq!(category("assets/minecraft/textures/item/diamond_sword.png"), Some(TextureCategory::Item));
+        assert_eq!(resource_type("assets/minecraft/textures/block/item_frame.png"), ResourceType::Block);
+        assert_eq!(resource_type("assets/minecraft/textures/entity/item_frame.png"), ResourceType::Entity);
+    }
+
+    #[test]
+    fn any_namespace_is_parsed() {
+        let texture = parse_texture_path(Path::new("assets/examplemod/textures/entity/golem/iron/body.png")).unwrap();
+        assert_eq!(texture.namespace, "examplemod");
+        assert_eq!(texture.category, TextureCategory::Entity);
+        assert_eq!(texture.name, PathBuf::from("golem/iron/body.png"));
+        assert_eq!(resource_type("assets/examplemod/textures/entity/golem/iron/body.png"), ResourceType::Entity);
+        assert_eq!(resource_type("assets/examplemod/textures/block/machine/front.png"), ResourceType::Block);
+    }
+
+    #[test]
+    fn legacy_plural_folders() {
+        assert_eq!(category("assets/minecraft/textures/blocks/stone.png"), Some(TextureCategory::Block));
+        assert_eq!(category("assets/minecraft/textures/items/stick.png"), Some(TextureCategory::Item));
+        assert_eq!(resource_type("assets/minecraft/textures/items/stick.png"), ResourceType::Item);
+    }
+
+    #[test]
+    fn categories_map_to_their_pipelines() {
+        assert_eq!(resource_type("assets/minecraft/textures/gui/sprites/hud/heart/full.png"), ResourceType::Nearest);
+        assert_eq!(resource_type("assets/minecraft/textures/font/ascii.png"), ResourceType::Nearest);
+        assert_eq!(resource_type("assets/minecraft/textures/particle/flame.png"), ResourceType::Item);
+        assert_eq!(resource_type("assets/minecraft/textures/models/armor/iron_layer_1.png"), ResourceType::Entity);
+        assert_eq!(resource_type("assets/minecraft/textures/colormap/grass.png"), ResourceType::NonImage);
+        assert_eq!(resource_type("assets/minecraft/textures/misc/pumpkinblur.png"), ResourceType::Block);
+        assert_eq!(resource_type("assets/examplemod/textures/machines/press.png"), ResourceType::Block);
+    }
+
+    #[test]
+    fn trim_palettes_are_copied_and_trim_items_smoothed() {
+        assert_eq!(resource_type("assets/minecraft/textures/trims/color_palettes/gold.png"), ResourceType::NonImage);
+        assert_eq!(resource_type("assets/minecraft/textures/trims/items/helmet_trim.png"), ResourceType::Item);
+        assert_eq!(resource_type("assets/minecraft/textures/trims/models/armor/coast.png"), ResourceType::Entity);
+    }
+
+    #[test]
+    fn files_without_an_extension_are_copied() {
+        assert_eq!(resource_type(".DS_Store"), ResourceType::NonImage);
+        assert_eq!(resource_type("LICENSE"), ResourceType::NonImage);
+        assert_eq!(resource_type("assets/minecraft/textures/block/.gitignore"), ResourceType::NonImage);
+    }
+
+    #[test]
+    fn paths_outside_the_texture_layout_are_not_parsed() {
+        assert_eq!(category("assets/minecraft/textures/block"), None);
+        assert_eq!(category("assets/minecraft/models/block/stone.json"), None);
+        assert_eq!(category("optifine/ctm/glass/0.png"), None);
+        assert_eq!(resource_type("optifine/ctm/glass/0.png"), ResourceType::Block);
+    }
+}
@@ -0,0 +1,220 @@
+use std::{collections::{BTreeMap, HashMap}, path::{Path, PathBuf}, sync::Mutex};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, error::{Error, Stage}, fs_operations::{write_atomically, ResourceSink, ResourceSource}};
+
+const CACHE_FILE: &str = ".almostdefault-cache.json";
+// Bump when the manifest layout changes, so older manifests are ignored rather than misread
+const CACHE_VERSION: u32 = 1;
+
+/// What an output was made from: a hash of the input bytes it depends on, and of how they were processed.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CacheKey {
+    input: String,
+    parameters: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct CacheEntry {
+    #[serde(flatten)]
+    key: CacheKey,
+    pub(crate) input_size: Option<(u32, u32)>,
+    pub(crate) output_size: Option<(u32, u32)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    entries: BTreeMap<PathBuf, CacheEntry>,
+}
+
+/// Remembers what each output in a directory output was made from, in `.almostdefault-cache.json`, so a
+/// later run into the same directory only redoes what changed. Zipped outputs are always rebuilt in full.
+pub(crate) struct Cache {
+    root_path: Option<PathBuf>,
+    previous: HashMap<PathBuf, CacheEntry>,
+    // `None` for resources that failed, so they're tried again next time
+    current: Mutex<HashMap<PathBuf, Option<CacheEntry>>>,
+}
+
+impl CacheKey {
+    /// Hashes `inputs` (the resource's own bytes, plus any other resource its output depends on) and `parameters`.
+    pub(crate) fn new(inputs: &[&[u8]], parameters: &str) -> CacheKey {
+        let mut hasher = blake3::Hasher::new();
+        for bytes in inputs {
+            // Length-prefixed, so moving bytes from one input to the next changes the hash
+            hasher.update(&(bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        }
+
+        return CacheKey {
+            input: hasher.finalize().to_hex().to_string(),
+            parameters: blake3::hash(parameters.as_bytes()).to_hex().to_string(),
+        };
+    }
+}
+
+impl CacheEntry {
+    pub(crate) fn new(key: CacheKey, input_size: Option<(u32, u32)>, output_size: Option<(u32, u32)>) -> CacheEntry {
+        return CacheEntry { key, input_size, output_size };
+    }
+}
+
+impl Cache {
+    /// Reads the manifest left in `sink` by an earlier run. A missing or unreadable manifest just means everything is processed.
+    pub(crate) fn load(sink: &ResourceSink) -> Cache {
+        let root_path = match sink {
+            ResourceSink::Directory(root_path) => root_path,
+            ResourceSink::Archive(_, _) => return Cache::disabled()
+        };
+
+        let previous = match std::fs::read(root_path.join(CACHE_FILE)) {
+            Ok(bytes) => match serde_json::from_slice::<Manifest>(&bytes) {
+                Ok(m) if m.version == CACHE_VERSION => m.entries.into_iter().collect(),
+                _ => HashMap::new()
+            },
+            Err(_e) => HashMap::new()
+        };
+
+        return Cache {
+            root_path: Some(root_path.to_owned()),
+            previous,
+            current: Mutex::new(HashMap::new()),
+        };
+    }
+
+    fn disabled() -> Cache {
+        return Cache {
+            root_path: None,
+            previous: HashMap::new(),
+            current: Mutex::new(HashMap::new()),
+        };
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        return self.root_path.is_some();
+    }
+
+    /// The earlier run's entry for `resource`, if it was made from the same inputs in the same way and is still there.
+    pub(crate) fn lookup(&self, resource: &Path, key: &CacheKey) -> Option<CacheEntry> {
+        let root_path = self.root_path.as_ref()?;
+        // Whatever was done to it since the manifest was read supersedes the manifest's entry
+        let entry = match self.current.lock().ok()?.get(resource) {
+            Some(e) => e.clone()?,
+            None => self.previous.get(resource)?.clone()
+        };
+        if entry.key != *key || !root_path.join(resource).is_file() {
+            return None;
+        }
+        return Some(entry);
+    }
+
+    /// Records what `resource`'s output was made from, or `None` if it couldn't be made.
+    pub(crate) fn record(&self, resource: &Path, entry: Option<CacheEntry>) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Ok(mut current) = self.current.lock() {
+            current.insert(resource.to_owned(), entry);
+        }
+    }
+
+    /// Removes the outputs made from `removed`, a deleted file or folder: the resource itself, or every resource
+    /// that was under it. Returns the resources whose outputs were removed.
+    pub(crate) fn remove(&self, removed: &Path) -> Result<Vec<PathBuf>, Error> {
+        let root_path = match &self.root_path {
+            Some(r) => r,
+            None => return Ok(Vec::new())
+        };
+        let resources: Vec<PathBuf> = match self.current.lock() {
+            Ok(current) => current.iter()
+                .filter_map(|(resource, entry)| entry.as_ref().map(|_e| resource))
+                .chain(self.previous.keys().filter(|r| !current.contains_key(*r)))
+                .filter(|r| r.starts_with(removed))
+                .cloned()
+                .collect(),
+            Err(_e) => return Err(Error::new(removed, Stage::Write, "cache is unavailable"))
+        };
+
+        let mut removed_outputs = Vec::new();
+        for resource in resources {
+            self.record(&resource, None);
+            if remove_output(root_path, &resource)? {
+                removed_outputs.push(resource);
+            }
+        }
+        removed_outputs.sort();
+        return Ok(removed_outputs);
+    }
+
+    /// Removes outputs whose inputs are gone from `source` or now skipped by `config`, and writes the manifest
+    /// for the next run. Entries for resources left out of this run (e.g. by `--include`) are kept as they were.
+    /// Returns how many outputs were removed.
+    pub(crate) fn save(&self, source: &ResourceSource, config: &Config) -> Result<usize, Error> {
+        let root_path = match &self.root_path {
+            Some(r) => r,
+            None => return Ok(0)
+        };
+        let mut current = match self.current.lock() {
+            Ok(c) => c,
+            Err(_e) => return Err(Error::new(&root_path.join(CACHE_FILE), Stage::Write, "cache is unavailable"))
+        };
+
+        let mut entries: BTreeMap<PathBuf, CacheEntry> = current.iter()
+            .filter_map(|(resource, entry)| Some((resource.to_owned(), entry.clone()?)))
+            .collect();
+
+        let mut removed = 0;
+        for (resource, entry) in &self.previous {
+            if current.contains_key(resource) {
+                continue;
+            }
+            if source.contains(resource) && !config.is_skipped(resource) {
+                entries.insert(resource.to_owned(), entry.clone());
+                continue;
+            }
+            if remove_output(root_path, resource)? {
+                removed += 1;
+            }
+            // So it isn't looked at again on the next save
+            current.insert(resource.to_owned(), None);
+        }
+
+        let manifest = Manifest { version: CACHE_VERSION, entries };
+        let manifest_path = root_path.join(CACHE_FILE);
+        let bytes = match serde_json::to_vec(&manifest) {
+            Ok(b) => b,
+            Err(e) => return Err(Error::new(&manifest_path, Stage::Encode, e))
+        };
+        match write_atomically(&manifest_path, &bytes) {
+            Ok(()) => return Ok(removed),
+            Err(e) => return Err(Error::new(&manifest_path, Stage::Write, e))
+        };
+    }
+}
+
+/// Whether `root_path` holds the output of an earlier run, which can be updated in place.
+pub(crate) fn has_manifest(root_path: &Path) -> bool {
+    return root_path.join(CACHE_FILE).is_file();
+}
+
+/// Deletes a stale output, along with any folders it leaves empty. Returns whether there was anything to delete.
+fn remove_output(root_path: &Path, resource: &Path) -> Result<bool, Error> {
+    let output = root_path.join(resource);
+    match std::fs::remove_file(&output) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(Error::new(resource, Stage::Write, e))
+    }
+
+    let mut folder = resource.parent();
+    while let Some(f) = folder {
+        // Fails, and so stops, at the first folder that still has something in it
+        if f.as_os_str().is_empty() || std::fs::remove_dir(root_path.join(f)).is_err() {
+            break;
+        }
+        folder = f.parent();
+    }
+
+    return Ok(true);
+}
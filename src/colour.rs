@@ -0,0 +1,86 @@
+use image::Rgba;
+
+use crate::ColourSpace;
+
+/// sRGB-encoded channel (0-255) to linear light (0-1).
+pub(crate) fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 {
+        return v / 12.92;
+    }
+    return ((v + 0.055) / 1.055).powf(2.4);
+}
+
+/// Linear RGB to Oklab (L, a, b), per https://bottosson.github.io/posts/oklab/
+pub(crate) fn linear_to_oklab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    return [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ];
+}
+
+pub(crate) fn srgb_to_oklab(colour: &Rgba<u8>) -> [f32; 3] {
+    let [r, g, b, _] = colour.0;
+    return linear_to_oklab([srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)]);
+}
+
+/// Linear light (0-1) back to an sRGB-encoded channel.
+pub(crate) fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let encoded = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    return (encoded * 255.0).round() as u8;
+}
+
+/// Oklab (L, a, b) back to linear RGB.
+pub(crate) fn oklab_to_linear(lab: [f32; 3]) -> [f32; 3] {
+    let [l, a, b] = lab;
+    let l_ = (l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let m_ = (l - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+    let s_ = (l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+
+    return [
+        4.076_741_7 * l_ - 3.307_711_6 * m_ + 0.230_969_94 * s_,
+        -1.268_438 * l_ + 2.609_757_4 * m_ - 0.341_319_38 * s_,
+        -0.004_196_086_3 * l_ - 0.703_418_6 * m_ + 1.707_614_7 * s_,
+    ];
+}
+
+/// A pixel's colour channels in `space`, with alpha (0-1) last.
+pub(crate) fn to_colour_space(colour: &Rgba<u8>, space: ColourSpace) -> [f32; 4] {
+    let [r, g, b, a] = colour.0;
+    let alpha = a as f32 / 255.0;
+    match space {
+        ColourSpace::Srgb => return [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, alpha],
+        ColourSpace::Linear => return [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), alpha],
+        ColourSpace::Oklab => {
+            let [l, ok_a, ok_b] = srgb_to_oklab(colour);
+            return [l, ok_a, ok_b, alpha];
+        }
+    }
+}
+
+pub(crate) fn from_colour_space(channels: [f32; 4], space: ColourSpace) -> Rgba<u8> {
+    let [c0, c1, c2, alpha] = channels;
+    let alpha = (alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match space {
+        ColourSpace::Srgb => {
+            let encode = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+            return Rgba([encode(c0), encode(c1), encode(c2), alpha]);
+        },
+        ColourSpace::Linear => return Rgba([linear_to_srgb(c0), linear_to_srgb(c1), linear_to_srgb(c2), alpha]),
+        ColourSpace::Oklab => {
+            let [r, g, b] = oklab_to_linear([c0, c1, c2]);
+            return Rgba([linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), alpha]);
+        }
+    }
+}
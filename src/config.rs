@@ -0,0 +1,149 @@
+use std::path::Path;
+use almostdefault::{MaskShape, Options, OptionsBuilder, TextureKind};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+
+/// What to do with the resources a rule matches.
+pub(crate) enum RuleAction {
+    /// Leave the resource out of the output entirely
+    Skip,
+    /// Write the resource out unchanged
+    Copy,
+    /// Upscale through the given pipeline, with the rule's own options
+    Upscale(TextureKind, Options),
+}
+
+struct Rule {
+    patterns: GlobSet,
+    action: RuleAction,
+}
+
+/// Per-path exceptions to the usual processing, read from a TOML file. Rules are checked in order and the first match wins:
+///
+/// ```toml
+/// [[rule]]
+/// match = ["assets/*/textures/gui/title/**"]
+/// action = "skip"
+///
+/// [[rule]]
+/// match = ["assets/minecraft/textures/block/*glass*.png"]
+/// action = "upscale"
+/// pipeline = "item"
+/// median = 3
+/// mask = "diamond"
+/// ```
+#[derive(Default)]
+pub(crate) struct Config {
+    rules: Vec<Rule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    #[serde(rename = "match")]
+    patterns: Vec<String>,
+    action: String,
+    pipeline: Option<String>,
+    scale: Option<u32>,
+    median: Option<u32>,
+    mask: Option<String>,
+}
+
+impl Config {
+    /// Reads a config file, filling in any options a rule doesn't set from `defaults`.
+    pub(crate) fn load(path: &Path, defaults: &OptionsBuilder) -> Result<Config, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(_e) => return Err(format!("Error: Unable to read config file '{0}'.", path.display()))
+        };
+
+        let file: ConfigFile = match toml::from_str(&text) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("Error: Invalid config file '{0}': {e}", path.display()))
+        };
+
+        let mut rules = Vec::new();
+        for (i, rule) in file.rules.into_iter().enumerate() {
+            match parse_rule(rule, defaults) {
+                Ok(r) => rules.push(r),
+                Err(msg) => return Err(format!("Error: Rule {0} in config file '{1}': {msg}", i + 1, path.display()))
+            }
+        }
+
+        return Ok(Config { rules });
+    }
+
+    /// The action of the first rule matching `resource`, if any do.
+    pub(crate) fn action_for(&self, resource: &Path) -> Option<&RuleAction> {
+        return self.rules.iter()
+            .find(|r| r.patterns.is_match(resource))
+            .map(|r| &r.action);
+    }
+
+    pub(crate) fn is_skipped(&self, resource: &Path) -> bool {
+        return matches!(self.action_for(resource), Some(RuleAction::Skip));
+    }
+}
+
+fn parse_rule(rule: RuleFile, defaults: &OptionsBuilder) -> Result<Rule, String> {
+    if rule.patterns.is_empty() {
+        return Err(String::from("'match' needs at least one pattern"));
+    }
+
+    let patterns = build_glob_set(&rule.patterns)?;
+
+    let has_options = rule.pipeline.is_some() || rule.scale.is_some() || rule.median.is_some() || rule.mask.is_some();
+    let action = match rule.action.as_str() {
+        "skip" | "copy" if has_options => return Err(format!("'{0}' rules take no pipeline or options", rule.action)),
+        "skip" => RuleAction::Skip,
+        "copy" => RuleAction::Copy,
+        "upscale" => {
+            let kind = match &rule.pipeline {
+                Some(p) => p.parse::<TextureKind>()?,
+                None => return Err(String::from("'upscale' rules need a pipeline: block, item, entity, smooth or nearest"))
+            };
+
+            let mut builder = defaults.clone();
+            if let Some(scale) = rule.scale {
+                builder = builder.scale(scale);
+            }
+            if let Some(median) = rule.median {
+                builder = builder.median(median);
+            }
+            if let Some(mask) = &rule.mask {
+                builder = builder.mask_shape(mask.parse::<MaskShape>()?);
+            }
+
+            match builder.build() {
+                Ok(o) => RuleAction::Upscale(kind, o),
+                Err(e) => return Err(e.to_string())
+            }
+        },
+        a => return Err(format!("unknown action '{a}'; expected skip, copy or upscale"))
+    };
+
+    return Ok(Rule { patterns, action });
+}
+
+/// Compiles patterns matched against paths within the pack. `*` stays within one folder; `**` crosses folders.
+pub(crate) fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for p in patterns {
+        match GlobBuilder::new(p).literal_separator(true).build() {
+            Ok(g) => builder.add(g),
+            Err(e) => return Err(format!("invalid pattern '{p}': {e}"))
+        };
+    }
+
+    match builder.build() {
+        Ok(s) => return Ok(s),
+        Err(e) => return Err(format!("invalid patterns: {e}"))
+    };
+}
@@ -0,0 +1,142 @@
+use std::{fmt, io, path::{Path, PathBuf}};
+
+/// What was being done to a resource when it failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stage {
+    /// Opening the input, or reading a resource from it
+    Read,
+    /// Turning a resource's bytes into an image or JSON
+    Decode,
+    Upscale,
+    /// Rewriting a pack.mcmeta or texture .mcmeta
+    Metadata,
+    /// Turning an image back into PNG bytes
+    Encode,
+    /// Creating the output, or writing a resource to it
+    Write,
+    /// A worker stopped without finishing its resource
+    Process,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self {
+            Stage::Read => "reading",
+            Stage::Decode => "decoding",
+            Stage::Upscale => "upscaling",
+            Stage::Metadata => "updating metadata",
+            Stage::Encode => "encoding",
+            Stage::Write => "writing",
+            Stage::Process => "processing"
+        };
+        return write!(f, "{stage}");
+    }
+}
+
+/// The underlying cause of an [`Error`].
+#[derive(Debug)]
+pub(crate) enum ErrorKind {
+    Io(io::Error),
+    Archive(zip::result::ZipError),
+    Image(image::ImageError),
+    Json(serde_json::Error),
+    Upscale(almostdefault::Error),
+    /// The resource or input isn't usable, for a reason not covered by a library error
+    Invalid(String),
+    /// The worker processing the resource panicked or was cancelled
+    Stopped,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Io(e) => return write!(f, "{e}"),
+            ErrorKind::Archive(e) => return write!(f, "{e}"),
+            ErrorKind::Image(e) => return write!(f, "{e}"),
+            ErrorKind::Json(e) => return write!(f, "{e}"),
+            ErrorKind::Upscale(e) => return write!(f, "{e}"),
+            ErrorKind::Invalid(message) => return write!(f, "{message}"),
+            ErrorKind::Stopped => return write!(f, "stopped unexpectedly")
+        }
+    }
+}
+
+impl From<io::Error> for ErrorKind {
+    fn from(e: io::Error) -> ErrorKind {
+        return ErrorKind::Io(e);
+    }
+}
+
+impl From<zip::result::ZipError> for ErrorKind {
+    fn from(e: zip::result::ZipError) -> ErrorKind {
+        return ErrorKind::Archive(e);
+    }
+}
+
+impl From<image::ImageError> for ErrorKind {
+    fn from(e: image::ImageError) -> ErrorKind {
+        return ErrorKind::Image(e);
+    }
+}
+
+impl From<serde_json::Error> for ErrorKind {
+    fn from(e: serde_json::Error) -> ErrorKind {
+        return ErrorKind::Json(e);
+    }
+}
+
+impl From<almostdefault::Error> for ErrorKind {
+    fn from(e: almostdefault::Error) -> ErrorKind {
+        return ErrorKind::Upscale(e);
+    }
+}
+
+impl From<&str> for ErrorKind {
+    fn from(message: &str) -> ErrorKind {
+        return ErrorKind::Invalid(String::from(message));
+    }
+}
+
+impl From<String> for ErrorKind {
+    fn from(message: String) -> ErrorKind {
+        return ErrorKind::Invalid(message);
+    }
+}
+
+/// A failure reading, processing or writing one resource, or the input / output as a whole.
+#[derive(Debug)]
+pub(crate) struct Error {
+    /// The resource within the pack, or the input / output path for failures not tied to one resource
+    pub(crate) resource: PathBuf,
+    pub(crate) stage: Stage,
+    pub(crate) kind: ErrorKind,
+}
+
+impl Error {
+    pub(crate) fn new(resource: &Path, stage: Stage, kind: impl Into<ErrorKind>) -> Error {
+        return Error {
+            resource: resource.to_owned(),
+            stage,
+            kind: kind.into(),
+        };
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{0}: {1} failed: {2}", self.resource.display(), self.stage, self.kind);
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(e) => return Some(e),
+            ErrorKind::Archive(e) => return Some(e),
+            ErrorKind::Image(e) => return Some(e),
+            ErrorKind::Json(e) => return Some(e),
+            ErrorKind::Upscale(e) => return Some(e),
+            ErrorKind::Invalid(_) | ErrorKind::Stopped => return None
+        }
+    }
+}
@@ -1,50 +1,441 @@
-use std::path::PathBuf;
+use std::{fs::File, io::{Read, Write}, path::{Path, PathBuf}, sync::Mutex};
+use globset::GlobSet;
 use tokio::fs;
 use walkdir::WalkDir;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
 
-pub fn read_source_files(root_path: &PathBuf) -> Result<Vec<PathBuf>, &'static str> {
+use crate::{config::build_glob_set, error::{Error, Stage}};
+
+/// Where resources are read from: an unpacked pack directory, a zipped pack, or a Minecraft client jar.
+pub enum ResourceSource {
+    Directory(PathBuf),
+    Archive(PathBuf, Mutex<ZipArchive<File>>),
+    ClientJar(PathBuf, Mutex<ZipArchive<File>>),
+}
+
+/// Where processed resources are written to: a directory, or a zipped pack.
+pub enum ResourceSink {
+    Directory(PathBuf),
+    Archive(PathBuf, Box<Mutex<ZipWriter<File>>>),
+}
+
+/// Which resources are read from the source, from the `--include` / `--exclude` patterns.
+pub struct ResourceFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    default_excludes: bool,
+}
+
+impl ResourceFilter {
+    /// With no `include` patterns everything is included, less anything `exclude` matches.
+    pub fn new(include: &[String], exclude: &[String], default_excludes: bool) -> Result<ResourceFilter, String> {
+        let include = if include.is_empty() { None } else { Some(build_glob_set(include)?) };
+
+        return Ok(ResourceFilter {
+            include,
+            exclude: build_glob_set(exclude)?,
+            default_excludes,
+        });
+    }
+
+    pub fn allows(&self, path: &Path) -> bool {
+        return self.skip_reason(path).is_none();
+    }
+
+    /// Why `path` is left out, if it is.
+    pub fn skip_reason(&self, path: &Path) -> Option<&'static str> {
+        if self.include.as_ref().is_some_and(|i| !i.is_match(path)) {
+            return Some("not matched by --include");
+        }
+        if self.exclude.is_match(path) {
+            return Some("matched by --exclude");
+        }
+
+        // These paths contain files that don't need to be copied or upscaled
+        if self.default_excludes {
+            let path_as_string = path.to_string_lossy();
+            if path_as_string.contains("realms")
+            || path_as_string.contains("title")
+            || path_as_string.contains("presets") {
+                return Some("realms, title screen or world preset file");
+            }
+        }
+
+        return None;
+    }
+}
+
+impl ResourceSource {
+    pub fn open(root_path: &PathBuf) -> Result<ResourceSource, Error> {
+        if root_path.is_dir() {
+            return Ok(ResourceSource::Directory(root_path.to_owned()));
+        }
+
+        if !is_zip_path(root_path) {
+            return Err(Error::new(root_path, Stage::Read, "input must be a directory or a .zip resource pack"));
+        }
+
+        let file = match File::open(root_path) {
+            Ok(f) => f,
+            Err(e) => return Err(Error::new(root_path, Stage::Read, e))
+        };
+
+        match ZipArchive::new(file) {
+            Ok(a) => return Ok(ResourceSource::Archive(root_path.to_owned(), Mutex::new(a))),
+            Err(e) => return Err(Error::new(root_path, Stage::Read, e))
+        };
+    }
+
+    /// Opens a client jar, e.g. `.minecraft/versions/1.20.4/1.20.4.jar`, so its assets can be read directly.
+    pub fn open_client_jar(jar_path: &PathBuf) -> Result<ResourceSource, Error> {
+        let file = match File::open(jar_path) {
+            Ok(f) => f,
+            Err(e) => return Err(Error::new(jar_path, Stage::Read, e))
+        };
+
+        match ZipArchive::new(file) {
+            Ok(a) => return Ok(ResourceSource::ClientJar(jar_path.to_owned(), Mutex::new(a))),
+            Err(e) => return Err(Error::new(jar_path, Stage::Read, e))
+        };
+    }
+
+    /// The directory, pack or jar being read from.
+    pub fn path(&self) -> &Path {
+        match self {
+            ResourceSource::Directory(root_path) | ResourceSource::Archive(root_path, _) | ResourceSource::ClientJar(root_path, _) => return root_path
+        }
+    }
+
+    /// Whether `resource` is in the source, whether or not it was listed for this run.
+    pub fn contains(&self, resource: &Path) -> bool {
+        match self {
+            ResourceSource::Directory(root_path) => return root_path.join(resource).is_file(),
+            ResourceSource::Archive(_archive_path, archive) | ResourceSource::ClientJar(_archive_path, archive) => {
+                match archive.lock() {
+                    Ok(a) => return a.index_for_name(&archive_entry_name(resource)).is_some(),
+                    Err(_e) => return false
+                }
+            }
+        }
+    }
+
+    pub fn read(&self, resource: &PathBuf) -> Result<Vec<u8>, Error> {
+        match self {
+            ResourceSource::Directory(root_path) => {
+                match std::fs::read(root_path.join(resource)) {
+                    Ok(b) => return Ok(b),
+                    Err(e) => return Err(Error::new(resource, Stage::Read, e))
+                };
+            },
+            ResourceSource::Archive(_archive_path, archive) | ResourceSource::ClientJar(_archive_path, archive) => {
+                let mut archive = match archive.lock() {
+                    Ok(a) => a,
+                    Err(_e) => return Err(Error::new(resource, Stage::Read, "input archive is unavailable"))
+                };
+                let mut entry = match archive.by_name(&archive_entry_name(resource)) {
+                    Ok(e) => e,
+                    Err(e) => return Err(Error::new(resource, Stage::Read, e))
+                };
+                let mut bytes = Vec::new();
+                match entry.read_to_end(&mut bytes) {
+                    Ok(_u) => return Ok(bytes),
+                    Err(e) => return Err(Error::new(resource, Stage::Read, e))
+                };
+            }
+        }
+    }
+}
+
+impl ResourceSink {
+    pub fn create(root_path: &PathBuf) -> Result<ResourceSink, Error> {
+        if !is_zip_path(root_path) {
+            return Ok(ResourceSink::Directory(root_path.to_owned()));
+        }
+
+        if let Some(parent) = root_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    return Err(Error::new(parent, Stage::Write, e));
+                }
+            }
+        }
+
+        // Built up beside the real output and renamed over it once finished, so an interrupted run never leaves a broken pack
+        let temp_path = temp_path(root_path);
+        match File::create(&temp_path) {
+            Ok(f) => return Ok(ResourceSink::Archive(root_path.to_owned(), Box::new(Mutex::new(ZipWriter::new(f))))),
+            Err(e) => return Err(Error::new(&temp_path, Stage::Write, e))
+        };
+    }
+
+    pub fn write(&self, resource: &PathBuf, bytes: &[u8]) -> Result<(), Error> {
+        match self {
+            ResourceSink::Directory(root_path) => {
+                match write_atomically(&root_path.join(resource), bytes) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => return Err(Error::new(resource, Stage::Write, e))
+                };
+            },
+            ResourceSink::Archive(_root_path, writer) => {
+                let mut writer = match writer.lock() {
+                    Ok(w) => w,
+                    Err(_e) => return Err(Error::new(resource, Stage::Write, "output archive is unavailable"))
+                };
+                let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+                if let Err(e) = writer.start_file(archive_entry_name(resource), options) {
+                    return Err(Error::new(resource, Stage::Write, e));
+                }
+                match writer.write_all(bytes) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => return Err(Error::new(resource, Stage::Write, e))
+                };
+            }
+        }
+    }
+
+    /// Where `resource` ends up: a file under the output directory, or an entry within the output archive.
+    pub fn output_path(&self, resource: &Path) -> PathBuf {
+        match self {
+            ResourceSink::Directory(root_path) | ResourceSink::Archive(root_path, _) => return root_path.join(resource)
+        }
+    }
+
+    /// Writes the central directory of a zipped output. Directory outputs need no finishing.
+    pub fn finish(self) -> Result<(), Error> {
+        match self {
+            ResourceSink::Directory(_) => return Ok(()),
+            ResourceSink::Archive(root_path, writer) => {
+                let writer = match writer.into_inner() {
+                    Ok(w) => w,
+                    Err(_e) => return Err(Error::new(&root_path, Stage::Write, "output archive is unavailable"))
+                };
+                if let Err(e) = writer.finish() {
+                    return Err(Error::new(&root_path, Stage::Write, e));
+                }
+                match std::fs::rename(temp_path(&root_path), &root_path) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => return Err(Error::new(&root_path, Stage::Write, e))
+                };
+            }
+        }
+    }
+
+    /// Throws away an unfinished zipped output, leaving any earlier one in place. Directory outputs are left
+    /// as they are, since each resource in them is written whole or not at all.
+    pub fn abandon(self) {
+        if let ResourceSink::Archive(root_path, writer) = self {
+            drop(writer);
+            let _ = std::fs::remove_file(temp_path(&root_path));
+        }
+    }
+}
+
+/// Lists the resources to process, along with those `filter` left out.
+pub fn read_source_files(source: &ResourceSource, filter: &ResourceFilter) -> Result<(Vec<PathBuf>, Vec<PathBuf>), Error> {
     let mut resource_rel_paths: Vec<PathBuf> = Vec::new();
-    let mut path_as_string;
-
-    for entry in WalkDir::new(&root_path)
-    .follow_links(false)
-    .into_iter()
-    .filter_map(Result::ok)
-    .filter(|e| !e.file_type().is_dir()) {
-        match entry.path().strip_prefix(&root_path) {
-            Ok(path) => {
-                path_as_string = path.to_string_lossy();
-                // These paths contain files that don't need to be copied or upscaled
-                if path_as_string.contains("realms") 
-                || path_as_string.contains("title") 
-                || path_as_string.contains("presets") {
+
+    match source {
+        ResourceSource::Directory(root_path) => {
+            for entry in WalkDir::new(root_path)
+            .follow_links(false)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| !e.file_type().is_dir()) {
+                match entry.path().strip_prefix(root_path) {
+                    Ok(path) => resource_rel_paths.push(path.to_owned()),
+                    Err(_e) => return Err(Error::new(entry.path(), Stage::Read, "unable to form relative path for resource"))
+                }
+            }
+        },
+        ResourceSource::Archive(archive_path, archive) | ResourceSource::ClientJar(archive_path, archive) => {
+            let mut archive = match archive.lock() {
+                Ok(a) => a,
+                Err(_e) => return Err(Error::new(archive_path, Stage::Read, "input archive is unavailable"))
+            };
+            for i in 0..archive.len() {
+                let entry = match archive.by_index(i) {
+                    Ok(e) => e,
+                    Err(e) => return Err(Error::new(archive_path, Stage::Read, e))
+                };
+                if entry.is_dir() {
                     continue;
                 }
-                resource_rel_paths.push(path.to_owned())
+                match entry.enclosed_name() {
+                    Some(path) => resource_rel_paths.push(path),
+                    None => return Err(Error::new(archive_path, Stage::Read, format!("unsafe entry path '{0}'", entry.name())))
+                }
+            }
+        }
+    }
+
+    // A client jar is mostly code and data; only its texture and model assets belong in a pack
+    if let ResourceSource::ClientJar(_, _) = source {
+        resource_rel_paths.retain(|path| is_client_jar_asset(path));
+    }
+
+    let (resource_rel_paths, filtered_out) = resource_rel_paths.into_iter().partition(|path| filter.allows(path));
+    return Ok((resource_rel_paths, filtered_out));
+}
+
+/// Writes to a temporary file beside `path` and renames it into place, so `path` is never left half-written.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let temp_path = temp_path(path);
+    let result = std::fs::write(&temp_path, bytes).and_then(|()| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    return result;
+}
+
+/// `block/stone.png` -> `block/.stone.png.tmp`
+fn temp_path(path: &Path) -> PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".tmp");
+    return path.with_file_name(file_name);
+}
+
+/// Whether either path is, or is inside, the other, once symlinks and relative paths are resolved.
+pub fn paths_overlap(a: &Path, b: &Path) -> bool {
+    let a = resolve_path(a);
+    let b = resolve_path(b);
+    return a.starts_with(&b) || b.starts_with(&a);
+}
+
+/// Makes `path` absolute, resolving symlinks in as much of it as exists so far.
+fn resolve_path(path: &Path) -> PathBuf {
+    let absolute = match std::path::absolute(path) {
+        Ok(p) => p,
+        Err(_e) => path.to_owned()
+    };
+
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return missing.iter().rev().fold(resolved, |p, name| p.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
             },
-            Err(_e) => return Err("Error: Unable to form relative-path for resource.")
+            _ => return absolute
+        }
+    }
+}
+
+/// Whether `path` is a directory with anything in it.
+pub fn is_non_empty_dir(path: &Path) -> bool {
+    match std::fs::read_dir(path) {
+        Ok(mut entries) => return entries.next().is_some(),
+        Err(_e) => return false
+    }
+}
+
+/// Deletes everything inside the output directory, for `--clean`.
+pub fn clean_output_directory(root_path: &Path) -> Result<(), Error> {
+    let entries = match std::fs::read_dir(root_path) {
+        Ok(e) => e,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(Error::new(root_path, Stage::Write, e))
+    };
+
+    for entry in entries {
+        let path = match entry {
+            Ok(e) => e.path(),
+            Err(e) => return Err(Error::new(root_path, Stage::Write, e))
+        };
+        let result = if path.is_dir() { std::fs::remove_dir_all(&path) } else { std::fs::remove_file(&path) };
+        if let Err(e) = result {
+            return Err(Error::new(&path, Stage::Write, e));
         }
     }
 
-    return Ok(resource_rel_paths);
+    return Ok(());
 }
 
-pub async fn create_output_directory_structure(write_root: &PathBuf, resources: &Vec<PathBuf>) -> Result<(), &'static str> {
+pub async fn create_output_directory_structure(sink: &ResourceSink, resources: &Vec<PathBuf>) -> Result<(), Error> {
+    // Archives have no real directories; entries carry their full path
+    let write_root = match sink {
+        ResourceSink::Directory(root_path) => root_path,
+        ResourceSink::Archive(_, _) => return Ok(())
+    };
+
+    // Also called as files appear while watching, so any folder may be new, along with its parents
     for r in resources {
-        // ToDo: Try and join these two conditions into a single 'if'
-        if let Some(parent_rel_path) = r.parent() {
-            let absolute_output_sub_dir = &write_root.join(parent_rel_path);
-            if !absolute_output_sub_dir.exists() {
-                match fs::create_dir_all(absolute_output_sub_dir).await {
-                    Ok(d) => d,
-                    Err(e) => {
-                        println!("{e}");
-                        return Err("Error: Unable to create directory in output structure.")
-                    }
-                }
+        let absolute_output_sub_dir = match r.parent() {
+            Some(parent_rel_path) => write_root.join(parent_rel_path),
+            None => continue
+        };
+        if !absolute_output_sub_dir.is_dir() {
+            match fs::create_dir_all(&absolute_output_sub_dir).await {
+                Ok(d) => d,
+                Err(e) => return Err(Error::new(&absolute_output_sub_dir, Stage::Write, e))
             }
         }
     }
 
     return Ok(());
-}
\ No newline at end of file
+}
+
+/// Finds the client jar for `version` under a `.minecraft` directory, using the launcher's default location when none is given.
+pub fn find_client_jar(version: &str, minecraft_dir: Option<PathBuf>) -> Result<PathBuf, Error> {
+    let minecraft_dir = match minecraft_dir {
+        Some(d) => d,
+        None => match default_minecraft_dir() {
+            Some(d) => d,
+            None => return Err(Error::new(Path::new(".minecraft"), Stage::Read, "unable to locate the .minecraft directory; please pass it with --minecraft-dir"))
+        }
+    };
+
+    let jar_path = minecraft_dir.join("versions").join(version).join(format!("{version}.jar"));
+    if !jar_path.is_file() {
+        return Err(Error::new(&jar_path, Stage::Read, "no client jar found for that Minecraft version; has it been launched at least once?"));
+    }
+
+    return Ok(jar_path);
+}
+
+fn default_minecraft_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        return std::env::var_os("APPDATA").map(|d| PathBuf::from(d).join(".minecraft"));
+    }
+
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    if cfg!(target_os = "macos") {
+        return Some(home.join("Library").join("Application Support").join("minecraft"));
+    }
+
+    return Some(home.join(".minecraft"));
+}
+
+/// Keeps `assets/<namespace>/textures/...` and `assets/<namespace>/models/...`, plus the jar's own pack.mcmeta and pack.png.
+fn is_client_jar_asset(path: &Path) -> bool {
+    let components: Vec<_> = path.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+
+    match components.as_slice() {
+        [file] => file == "pack.mcmeta" || file == "pack.png",
+        [assets, _namespace, category, _, ..] => {
+            assets == "assets"
+            && (category == "textures" || category == "models")
+            && !components[components.len() - 1].ends_with(".class")
+        },
+        _ => false
+    }
+}
+
+pub fn is_zip_path(path: &Path) -> bool {
+    match path.extension() {
+        Some(e) => e.eq_ignore_ascii_case("zip"),
+        None => false
+    }
+}
+
+/// Zip entries always use '/' as a separator, whatever the host platform uses.
+fn archive_entry_name(resource: &Path) -> String {
+    return resource.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+}
@@ -1,8 +1,68 @@
-use image::{ImageBuffer, Pixel, Rgba, RgbaImage};
+use std::{collections::VecDeque, str::FromStr};
+use image::{imageops::{self, FilterType}, GrayImage, ImageBuffer, Luma, Pixel, Rgba, RgbaImage};
 
-use crate::UpscalingParameters;
+use crate::{colour::{from_colour_space, srgb_to_oklab, to_colour_space}, BlockBorder, ColourDistance, ColourSpace, MedianMode, Options};
 
-pub(crate) async fn pixel_doubling_upscale(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, scale: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+pub(crate) fn upscale_block_image(source_img: &RgbaImage, upscaling_parameters: &Options, border: BlockBorder) -> RgbaImage {
+    let tileable = match border {
+        BlockBorder::Wrap => true,
+        BlockBorder::Mean => false,
+        BlockBorder::Auto => has_opaque_edges(source_img)
+    };
+
+    let intermediate_img = if tileable {
+        pad_wrapped(source_img)
+    } else {
+        pad_with_mean_colour(source_img)
+    };
+
+    let mut upscaled_img = median_upscale(&intermediate_img, upscaling_parameters, false);
+
+    upscaled_img = circular_filter(&intermediate_img, upscaled_img, upscaling_parameters);
+
+    let mut trimmed_upscaled_img = RgbaImage::new(source_img.width() * upscaling_parameters.scale as u32, source_img.height() * upscaling_parameters.scale as u32);
+
+    for y in 0..trimmed_upscaled_img.height() {
+        for x in 0..trimmed_upscaled_img.width() {
+            trimmed_upscaled_img.put_pixel(x, y, *upscaled_img.get_pixel(x + upscaling_parameters.scale as u32, y + upscaling_parameters.scale as u32))
+        }
+    }
+
+    return trimmed_upscaled_img;
+}
+
+pub(crate) fn upscale_item_image(source_img: &RgbaImage, upscaling_parameters: &Options) -> RgbaImage {
+    let upscaled_img = median_upscale(source_img, upscaling_parameters, upscaling_parameters.alpha_aware);
+
+    let mut filtered_img = circular_filter(source_img, upscaled_img, upscaling_parameters);
+    if upscaling_parameters.alpha_aware {
+        bleed_into_transparency(&mut filtered_img);
+    }
+
+    return filtered_img;
+}
+
+pub(crate) fn upscale_entity_image(source_img: &RgbaImage, upscaling_parameters: &Options) -> RgbaImage {
+    let upscaled_img = median_upscale_with_corner_pass(source_img, upscaling_parameters, upscaling_parameters.alpha_aware);
+
+    let mut filtered_img = circular_filter(source_img, upscaled_img, upscaling_parameters);
+    if upscaling_parameters.alpha_aware {
+        bleed_into_transparency(&mut filtered_img);
+    }
+
+    return filtered_img;
+}
+
+pub(crate) fn upscale_smooth_image(source_img: &RgbaImage, upscaling_parameters: &Options) -> RgbaImage {
+    let mut upscaled_img = median_upscale(source_img, upscaling_parameters, upscaling_parameters.alpha_aware);
+    if upscaling_parameters.alpha_aware {
+        bleed_into_transparency(&mut upscaled_img);
+    }
+
+    return upscaled_img;
+}
+
+pub(crate) fn pixel_doubling_upscale(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, scale: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
     let mut upscaled_img: image::ImageBuffer<Rgba<u8>, Vec<u8>> = RgbaImage::new(img.width() * scale, img.height() * scale);
 
     let mut y_offset = 0;
@@ -26,58 +86,375 @@ pub(crate) async fn pixel_doubling_upscale(img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
     return upscaled_img;
 }
 
-pub(crate) async fn median_upscale(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, upscaling_parameters: &UpscalingParameters) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, &'static str> {
-    let upscaled_img = pixel_doubling_upscale(img, upscaling_parameters.scale as u32).await;
+pub(crate) fn median_upscale(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, upscaling_parameters: &Options, alpha_aware: bool) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let upscaled_img = pixel_doubling_upscale(img, upscaling_parameters.scale as u32);
     let mut filtered_upscaled_img = upscaled_img.clone();
-    let wb = (upscaling_parameters.median -1) / 2;
 
-    let mut colours: Vec<&Rgba<u8>>;
+    window_filter(&upscaled_img, upscaling_parameters, alpha_aware, |x, y, mean_colour| {
+        filtered_upscaled_img.put_pixel(x, y, mean_colour);
+    });
 
-    for x in wb..(upscaled_img.width() as i32 - wb) {
-        for y in wb..(upscaled_img.height() as i32 - wb) {
-            colours = Vec::new();
-            // Inner loop to get 3x3 pixels around target pixel
+    return filtered_upscaled_img;
+ }
+
+pub(crate) fn median_upscale_with_corner_pass(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, upscaling_parameters: &Options, alpha_aware: bool) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let upscaled_img = pixel_doubling_upscale(img, upscaling_parameters.scale as u32);
+    let mut filtered_upscaled_img = upscaled_img.clone();
+
+    window_filter(&upscaled_img, upscaling_parameters, alpha_aware, |x, y, mean_colour| {
+        if mean_colour.channels()[3] != 0 {
+            filtered_upscaled_img.put_pixel(x, y, mean_colour);
+        }
+    });
+
+    return filtered_upscaled_img;
+ }
+
+/// With `alpha_aware`, fully transparent pixels don't take part in the colour vote; alpha is still the median of the whole window.
+fn window_filter(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, upscaling_parameters: &Options, alpha_aware: bool, put: impl FnMut(u32, u32, Rgba<u8>)) {
+    match (upscaling_parameters.median_mode, upscaling_parameters.colour_space) {
+        // Converting to linear light doesn't reorder any channel's values, so it would pick the very same medians
+        (MedianMode::Classic, ColourSpace::Srgb | ColourSpace::Linear) => median_filter(img, upscaling_parameters.median, alpha_aware, put),
+        (MedianMode::Classic, space) => colour_space_median_filter(img, upscaling_parameters.median, space, alpha_aware, put),
+        (MedianMode::Vector(distance), space) => vector_median_filter(img, upscaling_parameters.median, distance, space, alpha_aware, put)
+    }
+}
+
+/// Calls `put` with the per-channel median of the `window` x `window` square around every pixel
+/// that has a full window. Keeps a histogram per channel and slides it down each column, so each
+/// step only adds and removes one row instead of collecting and sorting the whole window.
+pub(crate) fn median_filter(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, window: i32, alpha_aware: bool, mut put: impl FnMut(u32, u32, Rgba<u8>)) {
+    let wb = (window - 1) / 2;
+    let width = img.width() as i32;
+    let height = img.height() as i32;
+    if width < window || height < window {
+        return;
+    }
+
+    // Same position as `sorted[len / 2]` in get_mean_colour
+    let rank = (window * window / 2) as u32;
+    let mut histograms = [ChannelHistogram::new(), ChannelHistogram::new(), ChannelHistogram::new(), ChannelHistogram::new()];
+
+    // How many pixels are in the colour histograms; fewer than the window when skipping transparent ones
+    let mut coloured: i32;
+
+    let add_row = |histograms: &mut [ChannelHistogram; 4], coloured: &mut i32, x: i32, y: i32, count: i32| {
+        for i in -wb..=wb {
+            let channels = img.get_pixel((x + i) as u32, y as u32).channels();
+            histograms[3].add(channels[3], count);
+            if alpha_aware && channels[3] == 0 {
+                continue;
+            }
+            for c in 0..3 {
+                histograms[c].add(channels[c], count);
+            }
+            *coloured += count;
+        }
+    };
+
+    for x in wb..(width - wb) {
+        for h in histograms.iter_mut() {
+            h.clear();
+        }
+        coloured = 0;
+        for y in 0..window - 1 {
+            add_row(&mut histograms, &mut coloured, x, y, 1);
+        }
+
+        for y in wb..(height - wb) {
+            add_row(&mut histograms, &mut coloured, x, y + wb, 1);
+            let alpha = histograms[3].select(rank);
+            let mean_colour = if alpha_aware && (alpha == 0 || coloured == 0) {
+                Rgba([0, 0, 0, 0])
+            } else {
+                let colour_rank = coloured as u32 / 2;
+                Rgba([histograms[0].select(colour_rank), histograms[1].select(colour_rank), histograms[2].select(colour_rank), alpha])
+            };
+            put(x as u32, y as u32, mean_colour);
+            add_row(&mut histograms, &mut coloured, x, y - wb, -1);
+        }
+    }
+}
+
+/// The per-channel median, taken after converting every pixel into `space` and converted back to
+/// sRGB for `put`.
+pub(crate) fn colour_space_median_filter(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, window: i32, space: ColourSpace, alpha_aware: bool, mut put: impl FnMut(u32, u32, Rgba<u8>)) {
+    let wb = (window - 1) / 2;
+    let width = img.width() as i32;
+    let converted: Vec<[f32; 4]> = img.pixels().map(|p| to_colour_space(p, space)).collect();
+
+    let rank = (window * window / 2) as usize;
+    let mut channels: [Vec<f32>; 4] = Default::default();
+
+    for x in wb..(width - wb) {
+        for y in wb..(img.height() as i32 - wb) {
+            for c in channels.iter_mut() {
+                c.clear();
+            }
             for i in -wb..=wb {
                 for j in -wb..=wb {
-                    colours.push(upscaled_img.get_pixel((x + i) as u32, (y + j) as u32));
+                    let pixel = converted[((y + j) * width + x + i) as usize];
+                    channels[3].push(pixel[3]);
+                    if alpha_aware && pixel[3] == 0.0 {
+                        continue;
+                    }
+                    for c in 0..3 {
+                        channels[c].push(pixel[c]);
+                    }
                 }
             }
-            let mean_colour = get_mean_colour(colours).await;
-            filtered_upscaled_img.put_pixel(x as u32, y as u32, mean_colour);
-            //filtered_upscaled_img = put_pixel_preserve_transparency(filtered_upscaled_img, x as u32, y as u32, mean_colour);
+
+            let alpha = *channels[3].select_nth_unstable_by(rank, f32::total_cmp).1;
+            if alpha_aware && (alpha == 0.0 || channels[0].is_empty()) {
+                put(x as u32, y as u32, Rgba([0, 0, 0, 0]));
+                continue;
+            }
+
+            let mut median = [0.0, 0.0, 0.0, alpha];
+            for c in 0..3 {
+                let colour_rank = channels[c].len() / 2;
+                median[c] = *channels[c].select_nth_unstable_by(colour_rank, f32::total_cmp).1;
+            }
+            put(x as u32, y as u32, from_colour_space(median, space));
         }
     }
+}
 
-    return Ok(filtered_upscaled_img);
- }
+/// Calls `put` with the colour in each `window` x `window` square that has the smallest total
+/// distance to every other pixel in it. Windows over pixel-doubled images hold only a handful of
+/// distinct colours, so distances are summed per distinct colour rather than per pixel.
+pub(crate) fn vector_median_filter(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, window: i32, distance: ColourDistance, space: ColourSpace, alpha_aware: bool, mut put: impl FnMut(u32, u32, Rgba<u8>)) {
+    let wb = (window - 1) / 2;
 
-pub(crate) async fn median_upscale_with_corner_pass(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, upscaling_parameters: &UpscalingParameters) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, &'static str> {
-    let upscaled_img = pixel_doubling_upscale(img, upscaling_parameters.scale as u32).await;
-    let mut filtered_upscaled_img = upscaled_img.clone();
-    let wb = (upscaling_parameters.median -1) / 2;
+    // (colour, its coordinates for `distance`, how many times it's in the window)
+    let mut distinct: Vec<(Rgba<u8>, [f32; 4], u32)> = Vec::new();
+    let mut alphas: Vec<u8> = Vec::new();
+    let rank = (window * window / 2) as usize;
+
+    for x in wb..(img.width() as i32 - wb) {
+        for y in wb..(img.height() as i32 - wb) {
+            distinct.clear();
+            alphas.clear();
+            for i in -wb..=wb {
+                for j in -wb..=wb {
+                    let colour = *img.get_pixel((x + i) as u32, (y + j) as u32);
+                    alphas.push(colour.0[3]);
+                    if alpha_aware && colour.0[3] == 0 {
+                        continue;
+                    }
+                    match distinct.iter_mut().find(|d| d.0 == colour) {
+                        Some(d) => d.2 += 1,
+                        None => distinct.push((colour, colour_coordinates(&colour, distance, space), 1))
+                    }
+                }
+            }
+
+            let total_distance = |point: &[f32; 4]| -> f32 {
+                distinct.iter()
+                    .map(|(_, other, count)| {
+                        let squared: f32 = point.iter().zip(other).map(|(a, b)| (a - b) * (a - b)).sum();
+                        squared.sqrt() * *count as f32
+                    })
+                    .sum()
+            };
+
+            let alpha = *alphas.select_nth_unstable(rank).1;
+            if alpha_aware && (alpha == 0 || distinct.is_empty()) {
+                put(x as u32, y as u32, Rgba([0, 0, 0, 0]));
+                continue;
+            }
+
+            // Ties go to the colour seen first, so results are repeatable
+            let mut best = distinct[0].0;
+            let mut best_distance = f32::MAX;
+            for (colour, point, _) in distinct.iter() {
+                let d = total_distance(point);
+                if d < best_distance {
+                    best = *colour;
+                    best_distance = d;
+                }
+            }
+            if alpha_aware {
+                best.0[3] = alpha;
+            }
+            put(x as u32, y as u32, best);
+        }
+    }
+}
+
+fn colour_coordinates(colour: &Rgba<u8>, distance: ColourDistance, space: ColourSpace) -> [f32; 4] {
+    let [r, g, b, a] = colour.0;
+    match distance {
+        ColourDistance::Rgb if space == ColourSpace::Srgb => return [r as f32, g as f32, b as f32, a as f32],
+        ColourDistance::Rgb => return to_colour_space(colour, space),
+        ColourDistance::Oklab => {
+            let [l, ok_a, ok_b] = srgb_to_oklab(colour);
+            return [l, ok_a, ok_b, a as f32 / 255.0];
+        }
+    }
+}
+
+/// The original per-pixel median: collects and sorts every window from scratch. Kept to check
+/// `median_filter` against.
+pub(crate) fn median_filter_reference(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, window: i32, alpha_aware: bool, mut put: impl FnMut(u32, u32, Rgba<u8>)) {
+    let wb = (window - 1) / 2;
 
     let mut colours: Vec<&Rgba<u8>>;
+    let mut alphas: Vec<u8>;
 
-    for x in wb..(upscaled_img.width() as i32 - wb) {
-        for y in wb..(upscaled_img.height() as i32 - wb) {
+    for x in wb..(img.width() as i32 - wb) {
+        for y in wb..(img.height() as i32 - wb) {
             colours = Vec::new();
+            alphas = Vec::new();
             // Inner loop to get 3x3 pixels around target pixel
             for i in -wb..=wb {
                 for j in -wb..=wb {
-                    colours.push(upscaled_img.get_pixel((x + i) as u32, (y + j) as u32));
+                    let colour = img.get_pixel((x + i) as u32, (y + j) as u32);
+                    alphas.push(colour.channels()[3]);
+                    if alpha_aware && colour.channels()[3] == 0 {
+                        continue;
+                    }
+                    colours.push(colour);
                 }
             }
-            let mean_colour = get_mean_colour(colours).await;
-            if mean_colour.channels()[3] != 0 {
-                filtered_upscaled_img.put_pixel(x as u32, y as u32, mean_colour);
+            if !alpha_aware {
+                put(x as u32, y as u32, get_mean_colour(colours));
+                continue;
+            }
+
+            alphas.sort();
+            let alpha = alphas[alphas.len() / 2];
+            if alpha == 0 || colours.is_empty() {
+                put(x as u32, y as u32, Rgba([0, 0, 0, 0]));
+                continue;
             }
+            let mut mean_colour = get_mean_colour(colours);
+            mean_colour.0[3] = alpha;
+            put(x as u32, y as u32, mean_colour);
         }
     }
+}
 
-    return Ok(filtered_upscaled_img);
- }
+/// Counts of each value of one channel, with a coarse count per 16 values so a rank can be found in at most 32 steps.
+struct ChannelHistogram {
+    fine: [u32; 256],
+    coarse: [u32; 16],
+}
+
+impl ChannelHistogram {
+    fn new() -> ChannelHistogram {
+        return ChannelHistogram { fine: [0; 256], coarse: [0; 16] };
+    }
+
+    fn clear(&mut self) {
+        self.fine = [0; 256];
+        self.coarse = [0; 16];
+    }
+
+    fn add(&mut self, value: u8, count: i32) {
+        self.fine[value as usize] = self.fine[value as usize].wrapping_add_signed(count);
+        self.coarse[(value >> 4) as usize] = self.coarse[(value >> 4) as usize].wrapping_add_signed(count);
+    }
+
+    /// The value that would be at index `rank` if the counted values were sorted.
+    fn select(&self, rank: u32) -> u8 {
+        let mut seen = 0;
+        let mut bucket = 0;
+        while seen + self.coarse[bucket] <= rank {
+            seen += self.coarse[bucket];
+            bucket += 1;
+        }
+
+        let mut value = bucket * 16;
+        while seen + self.fine[value] <= rank {
+            seen += self.fine[value];
+            value += 1;
+        }
+
+        return value as u8;
+    }
+}
+
+/// Gives fully transparent pixels the colour of the nearest visible pixel, leaving them transparent.
+/// Mipmapping averages transparent pixels' colours in, so without this item edges get dark fringes.
+pub(crate) fn bleed_into_transparency(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) {
+    let width = img.width();
+    let height = img.height();
+    let mut queue: VecDeque<(u32, u32)> = VecDeque::new();
+    let mut filled = vec![false; (width * height) as usize];
+
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel.0[3] != 0 {
+            filled[(y * width + x) as usize] = true;
+            queue.push_back((x, y));
+        }
+    }
+
+    // Breadth-first from every visible pixel, so each transparent pixel takes its colour from the closest one
+    while let Some((x, y)) = queue.pop_front() {
+        let [r, g, b, _] = img.get_pixel(x, y).0;
+        let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+        for (nx, ny) in neighbours {
+            if nx >= width || ny >= height || filled[(ny * width + nx) as usize] {
+                continue;
+            }
+            filled[(ny * width + nx) as usize] = true;
+            img.put_pixel(nx, ny, Rgba([r, g, b, 0]));
+            queue.push_back((nx, ny));
+        }
+    }
+}
+
+/// Surrounds the image with a 1-pixel border of the median colour of its edges.
+pub(crate) fn pad_with_mean_colour(source_img: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let mut intermediate_img: ImageBuffer<Rgba<u8>, Vec<u8>> = RgbaImage::new(source_img.width() + 2, source_img.height() + 2);
+    let mut corner_colours: Vec<&Rgba<u8>> = Vec::new();
+    let mut current_colour: &Rgba<u8>;
+
+    for y in 1..source_img.height() + 1 {
+        for x in 1..source_img.width() + 1 {
+            current_colour = source_img.get_pixel(x-1, y-1);
+            intermediate_img.put_pixel(x, y, current_colour.to_owned());
+
+            if y == 1 || y == source_img.height() || x == 1 || x == source_img.width() {
+                corner_colours.push(current_colour);
+            }
+        }
+    }
+
+    let mean_colour: Rgba<u8> = get_mean_colour(corner_colours);
+
+    for y in 0..intermediate_img.height() {
+        intermediate_img.put_pixel(0, y, mean_colour);
+        intermediate_img.put_pixel(intermediate_img.width() - 1, y, mean_colour);
+    }
+
+    for x in 0..intermediate_img.width() {
+        intermediate_img.put_pixel(x, 0, mean_colour);
+        intermediate_img.put_pixel(x, intermediate_img.height() - 1, mean_colour);
+    }
+
+    return intermediate_img;
+}
+
+/// Surrounds the image with a 1-pixel border taken from its opposite edges, as if it were tiled.
+pub(crate) fn pad_wrapped(source_img: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let width = source_img.width();
+    let height = source_img.height();
+
+    return ImageBuffer::from_fn(width + 2, height + 2, |x, y| {
+        *source_img.get_pixel((x + width - 1) % width, (y + height - 1) % height)
+    });
+}
+
+/// Textures that are fully opaque around their edges are assumed to be tiled, like stone or dirt.
+pub(crate) fn has_opaque_edges(img: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> bool {
+    return img.enumerate_pixels()
+        .filter(|(x, y, _)| *x == 0 || *y == 0 || *x == img.width() - 1 || *y == img.height() - 1)
+        .all(|(_, _, p)| p.channels()[3] == 255);
+}
 
- pub(crate) async fn get_mean_colour(colours: Vec<&Rgba<u8>>) -> Rgba<u8> {
+ pub(crate) fn get_mean_colour(colours: Vec<&Rgba<u8>>) -> Rgba<u8> {
     let mut r = Vec::new();
     let mut g = Vec::new();
     let mut b = Vec::new();
@@ -102,82 +479,204 @@ pub(crate) async fn median_upscale_with_corner_pass(img: &ImageBuffer<Rgba<u8>,
     return Rgba::from(mean_channels);
 }
 
-pub(crate) async fn circular_filter(source_img: &ImageBuffer<Rgba<u8>, Vec<u8>>, mut upscaled_img: ImageBuffer<Rgba<u8>, Vec<u8>>, upscaling_parameters: &UpscalingParameters) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, &'static str> {
+pub(crate) fn circular_filter(source_img: &ImageBuffer<Rgba<u8>, Vec<u8>>, mut upscaled_img: ImageBuffer<Rgba<u8>, Vec<u8>>, upscaling_parameters: &Options) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let scale = upscaling_parameters.scale as u32;
+    let mask = CellMask::new(&upscaling_parameters.mask_shape, scale);
+
     for y in 0..upscaled_img.height() {
         for x in 0..upscaled_img.width() {
-            let result = match compare_ssse(upscaling_parameters.scale, y as i32 % upscaling_parameters.scale, x as i32 % upscaling_parameters.scale).await {
-                Some(b) => b,
-                None => return Err("Error: Unable to perform circular comparison.")
-            };
-            if result {
-                upscaled_img.put_pixel(x, y, *source_img.get_pixel(x / upscaling_parameters.scale as u32,y / upscaling_parameters.scale as u32));
+            if mask.contains(x % scale, y % scale) {
+                upscaled_img.put_pixel(x, y, *source_img.get_pixel(x / scale, y / scale));
             }
         }
     }
-    return Ok(upscaled_img);
+    return upscaled_img;
 }
 
-// TODO: Refactor Rename
-// TODO: Try changing to Option<T> ?
-pub async fn compare_ssse(scale: i32, y_percent_scale: i32, x_percent_scale: i32) -> Option<bool>{
-    if scale == 2 {
-        return Some(true);
-    } else if scale == 4 && y_percent_scale <= 4 && x_percent_scale <= 4 {
-        return Some(compare_4x(y_percent_scale, x_percent_scale));
-    } else if scale == 8 && y_percent_scale <= 8 && x_percent_scale <= 8 {
-        return Some(compare_8x(y_percent_scale, x_percent_scale));
-    } else if scale == 16 && y_percent_scale <= 16 && x_percent_scale <= 16 {
-        return Some(compare_16x(y_percent_scale, x_percent_scale));
-    } else {
-        return None;
-    }
-}
-
-fn compare_4x(y_percent_scale: i32, x_percent_scale: i32) -> bool {
-    let array: [[bool; 4]; 4] = [
-        [false, true, true, false],
-        [true, true, true, true],
-        [true, true, true, true], 
-        [false, true, true, false]
-        ];
-
-    return array[y_percent_scale as usize][x_percent_scale as usize]
-}
-
-fn compare_8x(y_percent_scale: i32, x_percent_scale: i32) -> bool {
-    let array: [[bool; 8]; 8] = [
-        [false, false, true, true, true, true, false, false],
-        [false, true, true, true, true, true, true, false],
-        [true, true, true, true, true, true, true, true],
-        [true, true, true, true, true, true, true, true],
-        [true, true, true, true, true, true, true, true],
-        [true, true, true, true, true, true, true, true],
-        [false, true, true, true, true, true, true, false],
-        [false, false, true, true, true, true, false, false]
-        ];
-
-    return array[y_percent_scale as usize][x_percent_scale as usize]
-}
-
-fn compare_16x(y_percent_scale: i32, x_percent_scale: i32) -> bool {
-    let array: [[bool; 16]; 16] = [
-        [ false, false, false, false, false, true, true, true, true, true, true, false, false, false, false, false ],
-        [ false, false, false, true, true, true, true, true, true, true, true, true, true, false, false, false ],
-        [ false, false, true, true, true, true, true, true, true, true, true, true, true, true, false, false ],
-        [ false, true, true, true, true, true, true, true, true, true, true, true, true, true, true, false ],
-        [ false, true, true, true, true, true, true, true, true, true, true, true, true, true, true, false ],
-        [ true, true, true, true, true, true, true, true, true, true, true, true, true, true, true, true ],
-        [ true, true, true, true, true, true, true, true, true, true, true, true, true, true, true, true ],
-        [ true, true, true, true, true, true, true, true, true, true, true, true, true, true, true, true ],
-        [ true, true, true, true, true, true, true, true, true, true, true, true, true, true, true, true ],
-        [ true, true, true, true, true, true, true, true, true, true, true, true, true, true, true, true ],
-        [ true, true, true, true, true, true, true, true, true, true, true, true, true, true, true, true ],
-        [ false, true, true, true, true, true, true, true, true, true, true, true, true, true, true, false ],
-        [ false, true, true, true, true, true, true, true, true, true, true, true, true, true, true, false ],
-        [ false, false, true, true, true, true, true, true, true, true, true, true, true, true, false, false ],
-        [ false, false, false, true, true, true, true, true, true, true, true, true, true, false, false, false ],
-        [ false, false, false, false, false, true, true, true, true, true, true, false, false, false, false, false ] 
-        ];
-
-    return array[y_percent_scale as usize][x_percent_scale as usize]
-}
\ No newline at end of file
+/// The shape reset to the source colour in the middle of each upscaled pixel. Softer shapes leave more of the smoothing in place.
+#[derive(Clone, Debug)]
+pub enum MaskShape {
+    Circle,
+    /// |x|^n + |y|^n <= 1; 2 is a circle, larger exponents are squarer
+    Superellipse(f64),
+    Diamond,
+    /// Corner radius as a fraction of half the cell; 0 is a square, 1 is a circle
+    RoundedSquare(f64),
+    /// Light pixels of a greyscale image, stretched over the cell
+    Custom(GrayImage),
+}
+
+impl FromStr for MaskShape {
+    type Err = String;
+
+    /// Parses `circle`, `diamond`, `superellipse:<exponent>`, `rounded:<radius>` or `custom:<mask.png>`.
+    fn from_str(shape: &str) -> Result<MaskShape, String> {
+        let (name, value) = match shape.split_once(':') {
+            Some((n, v)) => (n, Some(v)),
+            None => (shape, None)
+        };
+
+        let number = |default: f64| -> Result<f64, String> {
+            match value {
+                Some(v) => v.parse::<f64>().map_err(|_| format!("'{v}' is not a number")),
+                None => Ok(default)
+            }
+        };
+
+        match name {
+            "circle" => return Ok(MaskShape::Circle),
+            "diamond" => return Ok(MaskShape::Diamond),
+            "superellipse" => {
+                let exponent = number(4.0)?;
+                if exponent <= 0.0 {
+                    return Err(String::from("the superellipse exponent must be greater than 0"));
+                }
+                return Ok(MaskShape::Superellipse(exponent));
+            },
+            "rounded" => {
+                let radius = number(0.5)?;
+                if !(0.0..=1.0).contains(&radius) {
+                    return Err(String::from("the corner radius must be between 0 and 1"));
+                }
+                return Ok(MaskShape::RoundedSquare(radius));
+            },
+            "custom" => {
+                let path = match value {
+                    Some(p) => p,
+                    None => return Err(String::from("a custom mask needs a path, e.g. custom:mask.png"))
+                };
+                match image::open(path) {
+                    Ok(i) => {
+                        let luma_alpha = i.to_luma_alpha8();
+                        // Transparent pixels count as dark, whatever their colour
+                        let mask = GrayImage::from_fn(i.width(), i.height(), |x, y| {
+                            let p = luma_alpha.get_pixel(x, y);
+                            if p.0[1] < 128 { Luma([0]) } else { Luma([p.0[0]]) }
+                        });
+                        return Ok(MaskShape::Custom(mask));
+                    },
+                    Err(_e) => return Err(format!("unable to read mask image '{path}'"))
+                }
+            },
+            _ => return Err(format!("unknown mask shape '{name}'; expected circle, diamond, superellipse, rounded or custom"))
+        }
+    }
+}
+
+/// Which pixels of each upscaled source pixel's `scale` x `scale` cell are reset to the source colour.
+pub(crate) struct CellMask {
+    size: u32,
+    cells: Vec<bool>,
+}
+
+impl CellMask {
+    pub(crate) fn new(shape: &MaskShape, scale: u32) -> CellMask {
+        match shape {
+            MaskShape::Circle => return CellMask::circle(scale),
+            MaskShape::Diamond => return CellMask::from_fn(scale, |u, v| u.abs() + v.abs() <= 1.0),
+            MaskShape::Superellipse(n) => return CellMask::from_fn(scale, |u, v| u.abs().powf(*n) + v.abs().powf(*n) <= 1.0),
+            MaskShape::RoundedSquare(r) => {
+                let inner = 1.0 - r;
+                return CellMask::from_fn(scale, |u, v| {
+                    let (u, v) = (u.abs(), v.abs());
+                    u <= inner || v <= inner || (u - inner).powi(2) + (v - inner).powi(2) <= r * r
+                });
+            },
+            MaskShape::Custom(img) => {
+                let resized = imageops::resize(img, scale, scale, FilterType::Nearest);
+                let cells = resized.pixels().map(|p| p.0[0] >= 128).collect();
+                return CellMask { size: scale, cells };
+            }
+        }
+    }
+
+    /// Builds a mask from a test on each cell centre, in coordinates running from -1 to 1 across the cell.
+    fn from_fn(scale: u32, inside: impl Fn(f64, f64) -> bool) -> CellMask {
+        // A little slack so centres lying exactly on the edge of a shape are kept, despite rounding
+        let slack = 1e-9;
+        let s = scale as f64;
+        let cells = (0..scale * scale).map(|i| {
+            let u = (2.0 * (i % scale) as f64 + 1.0 - s) / s;
+            let v = (2.0 * (i / scale) as f64 + 1.0 - s) / s;
+            inside(u * (1.0 - slack), v * (1.0 - slack))
+        }).collect();
+
+        return CellMask { size: scale, cells };
+    }
+
+    /// A circle touching the cell's edges, giving the rounded-corner look of the original 4x / 8x / 16x tables.
+    fn circle(scale: u32) -> CellMask {
+        // Compare cell centres against the circle in doubled coordinates, so everything stays in integers:
+        // (x + 0.5 - s/2)^2 + (y + 0.5 - s/2)^2 <= (s/2)^2
+        let s = scale as i64;
+        let cells = (0..scale * scale).map(|i| {
+            let dx = 2 * (i % scale) as i64 + 1 - s;
+            let dy = 2 * (i / scale) as i64 + 1 - s;
+            dx * dx + dy * dy <= s * s
+        }).collect();
+
+        return CellMask { size: scale, cells };
+    }
+
+    pub(crate) fn contains(&self, x: u32, y: u32) -> bool {
+        return self.cells[(y * self.size + x) as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CellMask;
+
+    // The hand-written tables circle masks replaced, one row per string with `#` for kept cells; packs made
+    // before then depend on them
+    const TABLE_4X: [&str; 4] = [
+        ".##.",
+        "####",
+        "####",
+        ".##.",
+    ];
+    const TABLE_8X: [&str; 8] = [
+        "..####..",
+        ".######.",
+        "########",
+        "########",
+        "########",
+        "########",
+        ".######.",
+        "..####..",
+    ];
+    const TABLE_16X: [&str; 16] = [
+        ".....######.....",
+        "...##########...",
+        "..############..",
+        ".##############.",
+        ".##############.",
+        "################",
+        "################",
+        "################",
+        "################",
+        "################",
+        "################",
+        ".##############.",
+        ".##############.",
+        "..############..",
+        "...##########...",
+        ".....######.....",
+    ];
+
+    fn assert_matches_table(scale: u32, table: &[&str]) {
+        let mask = CellMask::circle(scale);
+        for (y, row) in table.iter().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                assert_eq!(mask.contains(x as u32, y as u32), cell == '#', "{scale}x mask differs at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn circle_masks_match_the_original_tables() {
+        assert_matches_table(4, &TABLE_4X);
+        assert_matches_table(8, &TABLE_8X);
+        assert_matches_table(16, &TABLE_16X);
+    }
+}
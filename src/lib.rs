@@ -0,0 +1,353 @@
+//! Upscales Minecraft textures, making them "less edgy" in the process.
+//!
+//! ```
+//! use almostdefault::{upscale, Options, TextureKind};
+//! use image::RgbaImage;
+//!
+//! let options = Options::builder().scale(8).build().unwrap();
+//! let texture = RgbaImage::new(16, 16);
+//! let upscaled = upscale(&texture, &options, TextureKind::Block).unwrap();
+//! assert_eq!(upscaled.dimensions(), (128, 128));
+//! ```
+#![allow(clippy::needless_return)]
+
+use std::{fmt, str::FromStr};
+use image::RgbaImage;
+
+mod colour;
+mod image_manipulation;
+
+pub use image_manipulation::MaskShape;
+
+/// Which pipeline a texture goes through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+    /// Padded around its edges before smoothing, then trimmed back
+    Block,
+    /// Smoothed as-is
+    Item,
+    /// Smoothed as-is, never overwriting pixels with a fully transparent result
+    Entity,
+    /// Smoothed by the median alone, without keeping the middle of each pixel sharp
+    Smooth,
+    /// Scaled up without any smoothing, for pixel-exact textures such as fonts
+    Nearest,
+}
+
+impl FromStr for TextureKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<TextureKind, String> {
+        match kind {
+            "block" => return Ok(TextureKind::Block),
+            "item" => return Ok(TextureKind::Item),
+            "entity" => return Ok(TextureKind::Entity),
+            "smooth" => return Ok(TextureKind::Smooth),
+            "nearest" => return Ok(TextureKind::Nearest),
+            _ => return Err(format!("unknown pipeline '{kind}'; expected block, item, entity, smooth or nearest"))
+        }
+    }
+}
+
+/// How block textures are padded before smoothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockBorder {
+    /// Wrap around for textures with opaque edges, otherwise use the mean edge colour
+    Auto,
+    /// Pad with pixels from the opposite edge, so the smoothed texture tiles seamlessly
+    Wrap,
+    /// Pad with the mean colour of the texture's edges
+    Mean,
+}
+
+impl FromStr for BlockBorder {
+    type Err = String;
+
+    fn from_str(border: &str) -> Result<BlockBorder, String> {
+        match border {
+            "auto" => return Ok(BlockBorder::Auto),
+            "wrap" => return Ok(BlockBorder::Wrap),
+            "mean" => return Ok(BlockBorder::Mean),
+            _ => return Err(format!("unknown block border '{border}'; expected auto, wrap or mean"))
+        }
+    }
+}
+
+/// How the median filter picks a colour from each window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MedianMode {
+    /// The median of each channel on its own. Can produce colours that aren't in the window,
+    /// but is what packs have always been made with
+    Classic,
+    /// The window colour closest to all the others, so no new hues appear at edges
+    Vector(ColourDistance),
+}
+
+/// How far apart two colours are, for [`MedianMode::Vector`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColourDistance {
+    /// Straight-line distance between RGBA values, in the [`ColourSpace`] being filtered in
+    Rgb,
+    /// Straight-line distance in the perceptual Oklab colour space, plus alpha
+    Oklab,
+}
+
+impl FromStr for MedianMode {
+    type Err = String;
+
+    /// Parses `classic`, `vector` (RGB distance), `vector:rgb` or `vector:oklab`.
+    fn from_str(mode: &str) -> Result<MedianMode, String> {
+        match mode {
+            "classic" => return Ok(MedianMode::Classic),
+            "vector" | "vector:rgb" => return Ok(MedianMode::Vector(ColourDistance::Rgb)),
+            "vector:oklab" => return Ok(MedianMode::Vector(ColourDistance::Oklab)),
+            _ => return Err(format!("unknown median mode '{mode}'; expected classic, vector:rgb or vector:oklab"))
+        }
+    }
+}
+
+/// Which colour space textures are filtered in. Textures are converted in before the median and
+/// circular passes and back to sRGB afterwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColourSpace {
+    /// The raw sRGB values stored in the texture
+    Srgb,
+    /// Linear light, so bright and dark pixels are weighed by how much light they actually give off. Only changes
+    /// [`MedianMode::Vector`] distances; the classic median picks the same values as in sRGB
+    Linear,
+    /// The perceptual Oklab colour space; lightness and hue are filtered separately
+    Oklab,
+}
+
+impl FromStr for ColourSpace {
+    type Err = String;
+
+    fn from_str(space: &str) -> Result<ColourSpace, String> {
+        match space {
+            "srgb" => return Ok(ColourSpace::Srgb),
+            "linear" => return Ok(ColourSpace::Linear),
+            "oklab" => return Ok(ColourSpace::Oklab),
+            _ => return Err(format!("unknown colour space '{space}'; expected srgb, linear or oklab"))
+        }
+    }
+}
+
+/// Settings for [`upscale`]. Build with [`Options::builder`].
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub(crate) scale: i32,
+    pub(crate) median: i32,
+    // Left to follow the scale, rather than set
+    median_from_scale: bool,
+    pub(crate) median_mode: MedianMode,
+    pub(crate) colour_space: ColourSpace,
+    pub(crate) alpha_aware: bool,
+    pub(crate) mask_shape: MaskShape,
+    pub(crate) block_border: BlockBorder,
+}
+
+impl Options {
+    pub fn builder() -> OptionsBuilder {
+        return OptionsBuilder::default();
+    }
+
+    /// A builder starting from these options, to derive variations of them. A median width that wasn't set
+    /// still follows the scale, so changing the scale changes it too.
+    pub fn to_builder(&self) -> OptionsBuilder {
+        return OptionsBuilder {
+            scale: self.scale as u32,
+            median: if self.median_from_scale { None } else { Some(self.median as u32) },
+            median_mode: self.median_mode,
+            colour_space: self.colour_space,
+            alpha_aware: self.alpha_aware,
+            mask_shape: self.mask_shape.clone(),
+            block_border: self.block_border,
+        };
+    }
+
+    pub fn scale(&self) -> u32 {
+        return self.scale as u32;
+    }
+
+    pub fn median(&self) -> u32 {
+        return self.median as u32;
+    }
+
+    pub fn median_mode(&self) -> MedianMode {
+        return self.median_mode;
+    }
+
+    pub fn colour_space(&self) -> ColourSpace {
+        return self.colour_space;
+    }
+
+    pub fn alpha_aware(&self) -> bool {
+        return self.alpha_aware;
+    }
+
+    pub fn mask_shape(&self) -> &MaskShape {
+        return &self.mask_shape;
+    }
+
+    pub fn block_border(&self) -> BlockBorder {
+        return self.block_border;
+    }
+}
+
+/// Builds [`Options`], checking they make sense together.
+#[derive(Clone, Debug)]
+pub struct OptionsBuilder {
+    scale: u32,
+    median: Option<u32>,
+    median_mode: MedianMode,
+    colour_space: ColourSpace,
+    alpha_aware: bool,
+    mask_shape: MaskShape,
+    block_border: BlockBorder,
+}
+
+impl Default for OptionsBuilder {
+    fn default() -> OptionsBuilder {
+        return OptionsBuilder {
+            scale: 4,
+            median: None,
+            median_mode: MedianMode::Classic,
+            colour_space: ColourSpace::Srgb,
+            alpha_aware: false,
+            mask_shape: MaskShape::Circle,
+            block_border: BlockBorder::Auto,
+        };
+    }
+}
+
+impl OptionsBuilder {
+    /// How many times larger the output is; any whole number of 2 or more. Defaults to 4.
+    pub fn scale(mut self, scale: u32) -> OptionsBuilder {
+        self.scale = scale;
+        return self;
+    }
+
+    /// Width of the median filter window; must be odd. Defaults to half the scale, plus one.
+    pub fn median(mut self, median: u32) -> OptionsBuilder {
+        self.median = Some(median);
+        return self;
+    }
+
+    /// How each window's colour is chosen. Defaults to [`MedianMode::Classic`].
+    pub fn median_mode(mut self, median_mode: MedianMode) -> OptionsBuilder {
+        self.median_mode = median_mode;
+        return self;
+    }
+
+    /// Which colour space to filter in. Defaults to [`ColourSpace::Srgb`].
+    pub fn colour_space(mut self, colour_space: ColourSpace) -> OptionsBuilder {
+        self.colour_space = colour_space;
+        return self;
+    }
+
+    /// For the item, entity and smooth pipelines: leave fully transparent pixels out of the colour vote, and fill the
+    /// colour of transparent areas from nearby visible pixels so mipmaps don't get dark edges.
+    /// Defaults to false.
+    pub fn alpha_aware(mut self, alpha_aware: bool) -> OptionsBuilder {
+        self.alpha_aware = alpha_aware;
+        return self;
+    }
+
+    pub fn mask_shape(mut self, mask_shape: MaskShape) -> OptionsBuilder {
+        self.mask_shape = mask_shape;
+        return self;
+    }
+
+    pub fn block_border(mut self, block_border: BlockBorder) -> OptionsBuilder {
+        self.block_border = block_border;
+        return self;
+    }
+
+    pub fn build(self) -> Result<Options, Error> {
+        if self.scale < 2 || self.scale > i32::MAX as u32 {
+            return Err(Error::UnsupportedScale(self.scale));
+        }
+
+        // 4 -> 3, 8 -> 5, 16 -> 9, rounded up to the next odd width
+        let median = match self.median {
+            Some(m) => m,
+            None => (self.scale / 2 + 1) | 1
+        };
+
+        if median % 2 == 0 || median > i32::MAX as u32 {
+            return Err(Error::UnsupportedMedian(median));
+        }
+
+        return Ok(Options {
+            scale: self.scale as i32,
+            median: median as i32,
+            median_from_scale: self.median.is_none(),
+            median_mode: self.median_mode,
+            colour_space: self.colour_space,
+            alpha_aware: self.alpha_aware,
+            mask_shape: self.mask_shape,
+            block_border: self.block_border,
+        });
+    }
+}
+
+/// Why options couldn't be built, or a texture couldn't be upscaled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The scale was less than 2
+    UnsupportedScale(u32),
+    /// The median window was even
+    UnsupportedMedian(u32),
+    /// The texture has no pixels
+    EmptyImage,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsupportedScale(s) => return write!(f, "unsupported scale {s}; please choose a whole number of 2 or more"),
+            Error::UnsupportedMedian(m) => return write!(f, "unsupported median window {m}; please choose an odd number"),
+            Error::EmptyImage => return write!(f, "unable to upscale an empty image")
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Upscales a single texture by `options.scale()`, smoothing it with the pipeline for `kind`.
+pub fn upscale(img: &RgbaImage, options: &Options, kind: TextureKind) -> Result<RgbaImage, Error> {
+    if img.width() == 0 || img.height() == 0 {
+        return Err(Error::EmptyImage);
+    }
+
+    let upscaled = match kind {
+        TextureKind::Block => image_manipulation::upscale_block_image(img, options, options.block_border),
+        TextureKind::Item => image_manipulation::upscale_item_image(img, options),
+        TextureKind::Entity => image_manipulation::upscale_entity_image(img, options),
+        TextureKind::Smooth => image_manipulation::upscale_smooth_image(img, options),
+        TextureKind::Nearest => image_manipulation::pixel_doubling_upscale(img, options.scale as u32)
+    };
+
+    return Ok(upscaled);
+}
+
+/// Exposed for the tests and benchmarks only; not part of the stable API.
+#[doc(hidden)]
+pub mod bench {
+    use image::RgbaImage;
+
+    use crate::image_manipulation::{median_filter, median_filter_reference};
+
+    pub fn median_filtered(img: &RgbaImage, window: u32, alpha_aware: bool) -> RgbaImage {
+        let mut filtered_img = img.clone();
+        median_filter(img, window as i32, alpha_aware, |x, y, colour| filtered_img.put_pixel(x, y, colour));
+        return filtered_img;
+    }
+
+    pub fn median_filtered_reference(img: &RgbaImage, window: u32, alpha_aware: bool) -> RgbaImage {
+        let mut filtered_img = img.clone();
+        median_filter_reference(img, window as i32, alpha_aware, |x, y, colour| filtered_img.put_pixel(x, y, colour));
+        return filtered_img;
+    }
+}
@@ -1,140 +1,625 @@
-use std::{path::PathBuf, process::ExitCode};
-use clap::Parser;
-use fs_operations::{create_output_directory_structure, read_source_files};
-use resource_operations::{copy_resource, determine_resource_type, process_block_resource, process_item_resource, ResourceType};
+#![allow(clippy::needless_return, clippy::ptr_arg)]
 
-use crate::resource_operations::process_entity_resource;
+use std::{collections::{BTreeMap, HashMap}, path::{Path, PathBuf}, process::ExitCode, sync::Arc, thread, time::Instant};
+use almostdefault::{BlockBorder, ColourSpace, MaskShape, MedianMode, Options, OptionsBuilder};
+use cache::{has_manifest, Cache};
+use clap::{ArgGroup, Parser};
+use config::Config;
+use error::{Error, ErrorKind, Stage};
+use fs_operations::{clean_output_directory, create_output_directory_structure, find_client_jar, is_non_empty_dir, is_zip_path, paths_overlap, read_source_files, ResourceFilter, ResourceSink, ResourceSource};
+use pack_metadata::{pack_format_for_version, parse_format_range, PackMetadataOptions};
+use progress::{format_bytes, Progress};
+use report::Report;
+use resource_operations::{create_pack_metadata, determine_resource_type, options_for, process_resource, Outcome, ProcessingContext, ResourceType};
+use target_resolution::TargetResolution;
+use tokio::{sync::Semaphore, task::JoinSet};
+use watch::watch_source;
 
-pub mod fs_operations;
-pub mod resource_operations;
-pub mod image_manipulation;
+mod asset_layout;
+mod cache;
+mod config;
+mod error;
+mod fs_operations;
+mod resource_operations;
+mod pack_metadata;
+mod progress;
+mod report;
+mod target_resolution;
+mod texture_metadata;
+mod watch;
 
 #[derive(Parser)]
 #[command(about = "Upscales resource-pack textures, making them less edgy.", long_about = None)]
+#[command(group(ArgGroup::new("source").required(true).args(["input", "from_jar", "minecraft_version"])))]
 struct Args {
+    /// Pack directory or .zip resource pack to read textures from
     #[arg(short = 'i', long = "input")]
-    input: String,
+    input: Option<String>,
 
+    /// Minecraft client jar to read the vanilla textures and models from
+    #[arg(long = "from-jar")]
+    from_jar: Option<String>,
+
+    /// Minecraft version whose client jar should be read from the local .minecraft directory
+    #[arg(long = "minecraft-version")]
+    minecraft_version: Option<String>,
+
+    /// .minecraft directory to look for --minecraft-version in, if not in the launcher's default location
+    #[arg(long = "minecraft-dir", requires = "minecraft_version")]
+    minecraft_dir: Option<String>,
+
+    /// Directory to write to, or a path ending in .zip to write a zipped pack
     #[arg(short = 'o', long = "output",)]
     output: String,
 
     #[arg(short = 'x', long = "scale", default_value_t = 4)]
-    scale: i32
+    scale: u32,
+
+    /// Tile size in pixels to bring every texture up to, e.g. 128, working out each texture's scale from its own
+    /// tile size; for packs that mix resolutions. Textures already this size or larger are copied as they are
+    #[arg(long = "target-resolution", conflicts_with = "scale", value_parser = clap::value_parser!(u32).range(2..))]
+    target_resolution: Option<u32>,
+
+    /// Width of the median filter window; must be odd. Defaults to half the scale, plus one
+    #[arg(short = 'm', long = "median")]
+    median: Option<u32>,
+
+    /// How the median picks each colour: classic (per channel), vector:rgb or vector:oklab (closest colour in the window)
+    #[arg(long = "median-mode", default_value = "classic")]
+    median_mode: MedianMode,
+
+    /// Colour space to filter in: srgb, linear (linear light) or oklab
+    #[arg(long = "colour-space", default_value = "srgb")]
+    colour_space: ColourSpace,
+
+    /// For items and entities, leave transparent pixels out of the median and bleed colour into transparent areas
+    #[arg(long = "alpha-aware")]
+    alpha_aware: bool,
+
+    /// Shape kept sharp in each upscaled pixel: circle, diamond, superellipse:<exponent>, rounded:<radius 0-1> or custom:<mask.png>
+    #[arg(long = "mask", default_value = "circle")]
+    mask: MaskShape,
+
+    /// How to pad block textures before smoothing: auto, wrap (for tiling blocks) or mean (the mean edge colour)
+    #[arg(long = "block-border", default_value = "auto")]
+    block_border: BlockBorder,
+
+    /// Only read files matching this glob, e.g. "**/textures/block/*ore*". Can be given more than once
+    #[arg(long = "include")]
+    include: Vec<String>,
+
+    /// Leave out files matching this glob. Can be given more than once
+    #[arg(long = "exclude")]
+    exclude: Vec<String>,
+
+    /// Don't leave out the realms, title screen and world preset files that are skipped by default
+    #[arg(long = "no-default-excludes")]
+    no_default_excludes: bool,
+
+    /// TOML file of rules for skipping, copying or upscaling particular paths differently
+    #[arg(short = 'c', long = "config")]
+    config: Option<String>,
+
+    /// Description to write into the output pack.mcmeta
+    #[arg(long = "description")]
+    description: Option<String>,
+
+    /// pack_format to write into the output pack.mcmeta
+    #[arg(long = "pack-format", conflicts_with = "target_version")]
+    pack_format: Option<u32>,
+
+    /// Minecraft version the output pack targets, e.g. 1.20.4; sets pack_format to match
+    #[arg(long = "target-version")]
+    target_version: Option<String>,
+
+    /// supported_formats range for the output pack.mcmeta, e.g. 15-34
+    #[arg(long = "supported-formats", value_parser = parse_format_range)]
+    supported_formats: Option<(u32, u32)>,
+
+    /// Draw the scale factor (e.g. "x8") onto the upscaled pack.png
+    #[arg(long = "icon-badge")]
+    icon_badge: bool,
+
+    /// How many textures to process at once. Defaults to the number of CPU cores
+    #[arg(short = 'j', long = "jobs")]
+    jobs: Option<usize>,
+
+    /// Write a JSON report of how each file was classified and processed, e.g. to compare runs
+    #[arg(long = "report")]
+    report: Option<String>,
+
+    /// After processing, keep watching the input directory and update the output as files are added, changed or removed
+    #[arg(long = "watch", conflicts_with_all = ["from_jar", "minecraft_version"])]
+    watch: bool,
+
+    /// List what would be done with each file, and why any are skipped, without writing anything
+    #[arg(long = "dry-run", conflicts_with = "watch")]
+    dry_run: bool,
+
+    /// Write into an output directory that already has other files in it
+    #[arg(long = "force")]
+    force: bool,
+
+    /// Delete everything in the output directory before writing to it
+    #[arg(long = "clean", conflicts_with = "force")]
+    clean: bool
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
     let args: Args = Args::parse();
 
-    let read_root_path = PathBuf::from(args.input);
-    let resources = match read_source_files(&read_root_path) {
-        Ok(r) => r,
-        Err(msg) => {
-            println!("{0}", msg);
+    let upscaling_builder = get_upscaling_builder(&args);
+    let upscaling_parameters = match upscaling_builder.clone().build() {
+        Ok(u) => u,
+        Err(e) => {
+            println!("Error: {0}.", e);
             return ExitCode::FAILURE
         }
     };
 
-    let write_root_path = PathBuf::from(args.output);
-    match create_output_directory_structure(&write_root_path, &resources).await {
-        Ok(()) => (),
+    if upscaling_parameters.colour_space() == ColourSpace::Linear && upscaling_parameters.median_mode() == MedianMode::Classic {
+        println!("Warning: --colour-space linear has no effect with the classic median, which picks the same values as in srgb. Use --median-mode vector:rgb to smooth in linear light.");
+    }
+
+    let config = match &args.config {
+        Some(path) => match Config::load(&PathBuf::from(path), &upscaling_builder) {
+            Ok(c) => c,
+            Err(msg) => {
+                println!("{0}", msg);
+                return ExitCode::FAILURE
+            }
+        },
+        None => Config::default()
+    };
+
+    let source = match open_source(&args) {
+        Ok(s) => s,
         Err(msg) => {
-            println!("{0}", msg);
+            println!("Error: {0}", msg);
             return ExitCode::FAILURE
         }
+    };
+
+    let output_path = PathBuf::from(&args.output);
+    if paths_overlap(source.path(), &output_path) {
+        println!("Error: The output '{0}' overlaps the input '{1}'; please write to somewhere outside it.", output_path.display(), source.path().display());
+        return ExitCode::FAILURE
     }
 
-    let mapped_resources = match determine_resource_type(&resources) {
+    let filter = match ResourceFilter::new(&args.include, &args.exclude, !args.no_default_excludes) {
+        Ok(f) => f,
+        Err(msg) => {
+            println!("Error: {0}", msg);
+            return ExitCode::FAILURE
+        }
+    };
+
+    let (mut resources, filtered_out) = match read_source_files(&source, &filter) {
         Ok(r) => r,
         Err(msg) => {
-            println!("{0}", msg);
+            println!("Error: {0}", msg);
             return ExitCode::FAILURE
         }
     };
+    let config_skipped: Vec<PathBuf>;
+    (config_skipped, resources) = resources.into_iter().partition(|r| config.is_skipped(r));
+    if resources.is_empty() && !args.include.is_empty() {
+        println!("Warning: No files matched --include. Patterns match the whole path within the pack, e.g. \"**/block/*ore*\".");
+    }
 
-    let upscaling_parameters = match get_upscaling_parameters(args.scale) {
-        Ok(u) => u,
+    let mut skips: Vec<(PathBuf, &'static str)> = filtered_out.into_iter()
+        .map(|r| {
+            let reason = filter.skip_reason(&r).unwrap_or("filtered out");
+            (r, reason)
+        })
+        .collect();
+    skips.extend(config_skipped.into_iter().map(|r| (r, "skipped by a config rule")));
+
+    let mapped_resources = determine_resource_type(&resources, &config);
+
+    let pack_metadata_options = match get_pack_metadata_options(&args) {
+        Ok(p) => p,
         Err(msg) => {
             println!("{0}", msg);
             return ExitCode::FAILURE
         }
     };
+    // Sources without a pack.mcmeta (e.g. a bare texture folder) still need one to load as a pack
+    let create_pack_mcmeta = !pack_metadata_options.is_empty() && !resources.contains(&PathBuf::from("pack.mcmeta"));
 
-    let mut copy_tasks = Vec::new();
-    let mut block_tasks = Vec::new();
-    let mut item_tasks = Vec::new();
-    let mut entity_tasks = Vec::new();
+    // Measured over everything in the source, skipped files included, so a sheet's scale doesn't depend on the filters
+    let target = args.target_resolution.map(|resolution| {
+        let all_resources = resources.iter().chain(skips.iter().map(|(r, _reason)| r));
+        TargetResolution::measure(resolution, all_resources, &source)
+    });
 
-    for r in mapped_resources {
-       match r.1 {
-            ResourceType::NonImage => {
-                copy_tasks.push(copy_resource(&read_root_path, &write_root_path, r.0))
-            },
-            ResourceType::Block => {
-                block_tasks.push(process_block_resource(r.0, &read_root_path, &write_root_path, &upscaling_parameters))
-            },
-            ResourceType::Item => {
-                item_tasks.push(process_item_resource(r.0, &read_root_path, &write_root_path, &upscaling_parameters))
-            },
-            ResourceType::Entity => {
-                entity_tasks.push(process_entity_resource(r.0, &read_root_path, &write_root_path, &upscaling_parameters))
-            }
-        };
+    let mut report = Report::new(source.path(), &output_path);
+    for (r, reason) in &skips {
+        report.add_skipped(r, reason);
     }
 
-    for c in copy_tasks {
-        match c.await {
-            Ok(()) => (),
-            Err(msg) => {
-                println!("{0}", msg);
-                //return ExitCode::FAILURE
+    if args.dry_run {
+        print_plan(&mapped_resources, &skips, &config, &upscaling_parameters, &pack_metadata_options, create_pack_mcmeta, target.as_ref());
+        if let Some(path) = &args.report {
+            for (resource, resource_type) in &mapped_resources {
+                let pipeline = resource_type.pipeline().map(|k| (k, options_for(resource, &config, &upscaling_parameters)));
+                report.add(resource, resource_type.name(), pipeline, output_path.join(resource), Ok(Outcome::default()), None);
+            }
+            if let Err(msg) = report.write(Path::new(path)) {
+                println!("Error: {0}", msg);
+                return ExitCode::FAILURE
             }
         }
+        return ExitCode::SUCCESS
     }
 
-    for b in block_tasks {
-        match b.await {
-            Ok(()) => (),
-            Err(msg) => {
-                println!("{0}", msg);
-                //return ExitCode::FAILURE
+    // Zipped packs can't be updated in place, so watching needs directories on both sides
+    if args.watch && (!matches!(source, ResourceSource::Directory(_)) || is_zip_path(&output_path)) {
+        println!("Error: --watch needs a pack directory as input and a directory as output.");
+        return ExitCode::FAILURE
+    }
+
+    // An earlier run's output is updated in place, but anything else in the way needs saying what to do with
+    if !is_zip_path(&output_path) {
+        if args.clean {
+            if let Err(msg) = clean_output_directory(&output_path) {
+                println!("Error: {0}", msg);
+                return ExitCode::FAILURE
             }
+        } else if !args.force && is_non_empty_dir(&output_path) && !has_manifest(&output_path) {
+            println!("Error: The output directory '{0}' isn't empty. Use --force to write into it anyway, or --clean to empty it first.", output_path.display());
+            return ExitCode::FAILURE
         }
     }
 
-    for i in item_tasks {
-        match i.await {
+    let sink = match ResourceSink::create(&output_path) {
+        Ok(s) => s,
+        Err(msg) => {
+            println!("Error: {0}", msg);
+            return ExitCode::FAILURE
+        }
+    };
+
+    match create_output_directory_structure(&sink, &resources).await {
+        Ok(()) => (),
+        Err(msg) => {
+            println!("Error: {0}", msg);
+            return ExitCode::FAILURE
+        }
+    }
+
+    let progress = Progress::new(mapped_resources.len());
+
+    if create_pack_mcmeta {
+        match create_pack_metadata(&sink, &pack_metadata_options, &progress) {
             Ok(()) => (),
             Err(msg) => {
-                println!("{0}", msg);
-                //return ExitCode::FAILURE
+                println!("Error: {0}", msg);
+                return ExitCode::FAILURE
             }
         }
     }
 
-    for e in entity_tasks {
-        match e.await {
-            Ok(()) => (),
-            Err(msg) => {
-                println!("{0}", msg);
-                //return ExitCode::FAILURE
+    let jobs = match args.jobs {
+        Some(0) => {
+            println!("Error: --jobs must be at least 1.");
+            return ExitCode::FAILURE
+        },
+        Some(j) => j,
+        None => thread::available_parallelism().map_or(1, |n| n.get())
+    };
+
+    let cache = Cache::load(&sink);
+    let context = Arc::new(ProcessingContext {
+        source,
+        sink,
+        upscaling_parameters,
+        config,
+        pack_metadata_options,
+        icon_badge: args.icon_badge,
+        progress,
+        cache,
+        target,
+    });
+
+    // Only `jobs` textures are decoded / held in memory at once; the rest wait for a permit
+    let permits = Arc::new(Semaphore::new(jobs));
+    let mut tasks = JoinSet::new();
+    // So a worker that panics can still be reported against its resource
+    let mut task_resources = HashMap::new();
+    let total = mapped_resources.len();
+    let mut type_counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for resource_type in mapped_resources.values() {
+        *type_counts.entry(resource_type.name()).or_default() += 1;
+    }
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut interrupted = false;
+    let mut spawned = 0;
+
+    for (resource, resource_type) in mapped_resources {
+        let permit = tokio::select! {
+            biased;
+            // Every output is written whole or not at all, so what's in progress is finished and nothing more started
+            _ = &mut ctrl_c => {
+                interrupted = true;
+                break;
+            },
+            p = permits.clone().acquire_owned() => match p {
+                Ok(p) => p,
+                Err(_e) => {
+                    println!("Error: Unable to schedule resource processing.");
+                    return ExitCode::FAILURE
+                }
+            }
+        };
+        spawned += 1;
+        context.progress.set_category(resource_type.name());
+        let context = context.clone();
+        let task_resource = (resource.clone(), resource_type);
+        let task = tasks.spawn_blocking(move || {
+            let started = Instant::now();
+            let result = process_resource(resource, resource_type, &context);
+            let time = started.elapsed();
+            if let Err(e) = &result {
+                context.progress.println(&format!("Error: {e}"));
+            }
+            context.progress.finish_one();
+            drop(permit);
+            (result, time)
+        });
+        task_resources.insert(task.id(), task_resource);
+    }
+
+    let mut failures: Vec<(Error, ResourceType)> = Vec::new();
+    let mut counts = RunCounts { skipped: skips.len(), ..RunCounts::default() };
+    loop {
+        let task = tokio::select! {
+            // Still listened for once everything has been started, so Ctrl-C isn't ignored while the last resources finish
+            _ = &mut ctrl_c, if !interrupted => {
+                interrupted = true;
+                continue;
+            },
+            t = tasks.join_next_with_id() => match t {
+                Some(t) => t,
+                None => break
             }
+        };
+        let (id, result, time) = match task {
+            Ok((id, (result, time))) => (id, Some(result), Some(time)),
+            Err(e) => (e.id(), None, None)
+        };
+        let (resource, resource_type) = task_resources.remove(&id).unwrap_or((PathBuf::new(), ResourceType::NonImage));
+        let result = match result {
+            Some(r) => r,
+            // The worker stopped before it could report itself
+            None => {
+                let error = Error::new(&resource, Stage::Process, ErrorKind::Stopped);
+                context.progress.println(&format!("Error: {error}"));
+                context.progress.finish_one();
+                Err(error)
+            }
+        };
+
+        let options = context.options_for(&resource);
+        let applied = context.target.as_ref().map(|_t| applied_options(options, &result));
+        let pipeline = match &applied {
+            Some(a) => resource_type.pipeline().zip(a.as_ref()),
+            None => resource_type.pipeline().map(|k| (k, options))
+        };
+        report.add(&resource, resource_type.name(), pipeline, context.sink.output_path(&resource), result.as_ref().copied(), time);
+        match result {
+            Ok(o) if o.cached => counts.unchanged += 1,
+            Ok(o) if o.kept || matches!(resource_type, ResourceType::NonImage) => counts.copied += 1,
+            Ok(_o) => (),
+            Err(e) => failures.push((e, resource_type))
+        }
+    }
+    context.progress.finish();
+
+    counts.removed = match context.cache.save(&context.source, &context.config) {
+        Ok(r) => r,
+        Err(msg) => {
+            println!("Error: {0}", msg);
+            return ExitCode::FAILURE
+        }
+    };
+
+    if interrupted {
+        if spawned < total {
+            println!("Interrupted: {0} of {total} resources were not processed.", total - spawned);
+        } else {
+            println!("Interrupted: every resource had already been started, so they were all finished.");
+        }
+        if let Ok(c) = Arc::try_unwrap(context) {
+            c.sink.abandon();
+        }
+        // The conventional status for a program stopped by Ctrl-C
+        return ExitCode::from(130)
+    }
+
+    if let Some(path) = &args.report {
+        if let Err(msg) = report.write(Path::new(path)) {
+            println!("Error: {0}", msg);
+            return ExitCode::FAILURE
+        }
+    }
+
+    counts.failed = failures.len();
+    print_summary(&type_counts, &counts, &context.progress, jobs);
+
+    if !failures.is_empty() {
+        failures.sort_by(|(a, _), (b, _)| a.resource.cmp(&b.resource));
+        println!();
+        println!("{0} of {total} resources failed:", failures.len());
+        for (f, _t) in &failures {
+            println!("  {f}");
+        }
+    }
+
+    if args.watch {
+        return watch_source(context, filter, jobs).await;
+    }
+
+    let context = match Arc::try_unwrap(context) {
+        Ok(c) => c,
+        Err(_c) => {
+            println!("Error: Output is still in use.");
+            return ExitCode::FAILURE
+        }
+    };
+
+    match context.sink.finish() {
+        Ok(()) => (),
+        Err(msg) => {
+            println!("Error: {0}", msg);
+            return ExitCode::FAILURE
         }
     }
+
+    if !failures.is_empty() {
+        return ExitCode::FAILURE
+    }
+
     println!("All resources processed!");
     ExitCode::SUCCESS
 }
 
-fn get_upscaling_parameters(scale: i32) -> Result<UpscalingParameters, &'static str> {
-    match scale {
-        4 => return Ok(UpscalingParameters { scale: 4, median: 3}),
-        8 => return Ok(UpscalingParameters { scale: 8, median: 5}),
-        16 => return Ok(UpscalingParameters { scale: 16, median: 9}),
-        _ => return Err("Error: Unsupported scale. Please choose 4, 8 or 16.")
+/// The options a texture was upscaled with under `--target-resolution`, where its scale depended on its size and
+/// so is read back from the sizes. `None` if it was copied as it was, or failed.
+fn applied_options(options: &Options, result: &Result<Outcome, Error>) -> Option<Options> {
+    let (input_width, output_width) = match result {
+        Ok(Outcome { input_size: Some((w, _h)), output_size: Some((o, _p)), .. }) => (*w, *o),
+        _ => return None
+    };
+    if input_width == 0 || output_width <= input_width {
+        return None;
+    }
+    return options.to_builder().scale(output_width / input_width).build().ok();
+}
+
+/// Lists what a run would do with each resource, for `--dry-run`.
+#[allow(clippy::too_many_arguments)]
+fn print_plan(mapped_resources: &HashMap<PathBuf, ResourceType>, skips: &[(PathBuf, &'static str)], config: &Config,
+              upscaling_parameters: &Options, pack_metadata_options: &PackMetadataOptions, create_pack_mcmeta: bool, target: Option<&TargetResolution>) {
+    let mut planned: Vec<(&PathBuf, ResourceType)> = mapped_resources.iter().map(|(r, t)| (r, *t)).collect();
+    planned.sort_by_key(|(a, _)| *a);
+
+    let mut upscaled = 0;
+    for (resource, resource_type) in &planned {
+        // `None` for textures already at the target resolution, which are copied as they are
+        let scale = match target {
+            Some(t) => t.planned_scale(resource),
+            None => Some(options_for(resource, config, upscaling_parameters).scale())
+        };
+        let action = match (resource_type, scale) {
+            (ResourceType::NonImage, _) => String::from("copy"),
+            (ResourceType::PackMetadata, _) if pack_metadata_options.is_empty() => String::from("copy"),
+            (ResourceType::PackMetadata, _) => String::from("rewrite"),
+            (_, None) => String::from("copy (already at target)"),
+            (ResourceType::TextureMetadata, Some(s)) => format!("scale frame sizes x{s}"),
+            (ResourceType::AnimatedBlock, Some(s)) => format!("upscale block x{s}, by frame"),
+            (ResourceType::PackIcon, Some(s)) => format!("upscale pack icon x{s}"),
+            (t, Some(s)) => format!("upscale {0} x{s}", t.name())
+        };
+        if resource_type.pipeline().is_some() && scale.is_some() {
+            upscaled += 1;
+        }
+        println!("  {action:<30} {0}", resource.display());
+    }
+    if create_pack_mcmeta {
+        println!("  {0:<30} pack.mcmeta", "create");
+    }
+
+    let mut skips: Vec<&(PathBuf, &'static str)> = skips.iter().collect();
+    skips.sort_by_key(|(a, _)| a);
+    for (resource, reason) in skips.iter() {
+        println!("  {0:<30} {1} ({reason})", "skip", resource.display());
+    }
+
+    println!();
+    println!("{upscaled} to upscale, {0} to copy or update, {1} to skip. Dry run: nothing was written.", planned.len() - upscaled, skips.len());
+}
+
+/// What happened to the resources in a run, beyond their types.
+#[derive(Default)]
+struct RunCounts {
+    copied: usize,
+    unchanged: usize,
+    failed: usize,
+    skipped: usize,
+    removed: usize,
+}
+
+fn print_summary(type_counts: &BTreeMap<&'static str, usize>, counts: &RunCounts, progress: &Progress, jobs: usize) {
+    println!();
+    println!("Resources");
+    for (name, count) in type_counts {
+        println!("  {name:<18} {count:>10}");
+    }
+    println!("  {0:<18} {1:>10}", "copied", counts.copied);
+    println!("  {0:<18} {1:>10}", "unchanged", counts.unchanged);
+    println!("  {0:<18} {1:>10}", "failed", counts.failed);
+    println!("  {0:<18} {1:>10}", "skipped", counts.skipped);
+    println!("  {0:<18} {1:>10}", "removed", counts.removed);
+    println!("  {0:<18} {1:>10}", "bytes written", format_bytes(progress.bytes_written()));
+
+    println!("Time per stage (summed across {jobs} worker threads)");
+    for (stage, time) in progress.stage_times() {
+        println!("  {0:<18} {1:>9.2}s", stage.to_string(), time.as_secs_f64());
     }
+    println!("  {0:<18} {1:>9.2}s", "total (wall clock)", progress.elapsed().as_secs_f64());
 }
 
-struct UpscalingParameters {
-    scale: i32,
-    median: i32
-}
\ No newline at end of file
+fn open_source(args: &Args) -> Result<ResourceSource, Error> {
+    if let Some(input) = &args.input {
+        return ResourceSource::open(&PathBuf::from(input));
+    }
+
+    if let Some(jar) = &args.from_jar {
+        return ResourceSource::open_client_jar(&PathBuf::from(jar));
+    }
+
+    match &args.minecraft_version {
+        Some(version) => {
+            let jar = find_client_jar(version, args.minecraft_dir.as_ref().map(PathBuf::from))?;
+            return ResourceSource::open_client_jar(&jar);
+        },
+        None => return Err(Error::new(&PathBuf::new(), Stage::Read, "no input given"))
+    }
+}
+
+fn get_pack_metadata_options(args: &Args) -> Result<PackMetadataOptions, &'static str> {
+    let pack_format = match &args.target_version {
+        Some(version) => match pack_format_for_version(version) {
+            Some(f) => Some(f),
+            None => return Err("Error: Unknown target Minecraft version. Please set --pack-format instead.")
+        },
+        None => args.pack_format
+    };
+
+    return Ok(PackMetadataOptions {
+        description: args.description.clone(),
+        pack_format,
+        supported_formats: args.supported_formats,
+    });
+}
+
+/// The upscaling options given on the command line, which config rules start from.
+fn get_upscaling_builder(args: &Args) -> OptionsBuilder {
+    let mut builder = Options::builder()
+        .scale(args.scale)
+        .median_mode(args.median_mode)
+        .colour_space(args.colour_space)
+        .alpha_aware(args.alpha_aware)
+        .mask_shape(args.mask.clone())
+        .block_border(args.block_border);
+
+    if let Some(median) = args.median {
+        builder = builder.median(median);
+    }
+
+    return builder;
+}
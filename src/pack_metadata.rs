@@ -0,0 +1,173 @@
+use image::{Rgba, RgbaImage};
+use serde_json::{json, Map, Value};
+
+use crate::error::ErrorKind;
+
+/// Overrides for the output pack's `pack.mcmeta`. Anything left as `None` is kept from the source pack.
+#[derive(Debug, Default)]
+pub(crate) struct PackMetadataOptions {
+    pub(crate) description: Option<String>,
+    pub(crate) pack_format: Option<u32>,
+    pub(crate) supported_formats: Option<(u32, u32)>,
+}
+
+impl PackMetadataOptions {
+    pub(crate) fn is_empty(&self) -> bool {
+        return self.description.is_none() && self.pack_format.is_none() && self.supported_formats.is_none();
+    }
+}
+
+type Version = (u32, u32, u32);
+
+// (first version, last version, pack_format) for resource packs
+const PACK_FORMATS: [(Version, Version, u32); 21] = [
+    ((1, 6, 1), (1, 8, 9), 1),
+    ((1, 9, 0), (1, 10, 2), 2),
+    ((1, 11, 0), (1, 12, 2), 3),
+    ((1, 13, 0), (1, 14, 4), 4),
+    ((1, 15, 0), (1, 16, 1), 5),
+    ((1, 16, 2), (1, 16, 5), 6),
+    ((1, 17, 0), (1, 17, 1), 7),
+    ((1, 18, 0), (1, 18, 2), 8),
+    ((1, 19, 0), (1, 19, 2), 9),
+    ((1, 19, 3), (1, 19, 3), 12),
+    ((1, 19, 4), (1, 19, 4), 13),
+    ((1, 20, 0), (1, 20, 1), 15),
+    ((1, 20, 2), (1, 20, 2), 18),
+    ((1, 20, 3), (1, 20, 4), 22),
+    ((1, 20, 5), (1, 20, 6), 32),
+    ((1, 21, 0), (1, 21, 1), 34),
+    ((1, 21, 2), (1, 21, 3), 42),
+    ((1, 21, 4), (1, 21, 4), 46),
+    ((1, 21, 5), (1, 21, 5), 55),
+    ((1, 21, 6), (1, 21, 6), 63),
+    ((1, 21, 7), (1, 21, 8), 64),
+];
+
+/// Maps a Minecraft release such as "1.20.4" to the resource `pack_format` it expects.
+pub(crate) fn pack_format_for_version(version: &str) -> Option<u32> {
+    let mut parts = version.trim().split('.').map(|p| p.parse::<u32>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next()?.ok()?;
+    let patch = match parts.next() {
+        Some(p) => p.ok()?,
+        None => 0
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let v = (major, minor, patch);
+    return PACK_FORMATS.iter()
+        .find(|(first, last, _)| *first <= v && v <= *last)
+        .map(|(_, _, format)| *format);
+}
+
+/// Parses a `supported_formats` range written as "min-max", or a single format.
+pub(crate) fn parse_format_range(range: &str) -> Result<(u32, u32), String> {
+    let (min, max) = match range.split_once('-') {
+        Some((min, max)) => (min.trim(), max.trim()),
+        None => (range.trim(), range.trim())
+    };
+
+    let min: u32 = min.parse().map_err(|_| format!("'{min}' is not a pack format"))?;
+    let max: u32 = max.parse().map_err(|_| format!("'{max}' is not a pack format"))?;
+    if min > max {
+        return Err(String::from("the first pack format must not be greater than the second"));
+    }
+
+    return Ok((min, max));
+}
+
+/// Applies `options` on top of the source pack's `pack.mcmeta`, keeping any other sections (filters, overlays, languages).
+pub(crate) fn build_pack_mcmeta(existing: Option<&[u8]>, options: &PackMetadataOptions) -> Result<Vec<u8>, ErrorKind> {
+    let mut root = match existing {
+        Some(bytes) => match serde_json::from_slice::<Value>(bytes)? {
+            Value::Object(o) => o,
+            _ => return Err(ErrorKind::from("source pack.mcmeta is not a JSON object"))
+        },
+        None => Map::new()
+    };
+
+    let pack = match root.entry("pack").or_insert_with(|| json!({})) {
+        Value::Object(p) => p,
+        _ => return Err(ErrorKind::from("source pack.mcmeta has an invalid 'pack' section"))
+    };
+
+    if let Some(description) = &options.description {
+        pack.insert(String::from("description"), json!(description));
+    }
+    if let Some(format) = options.pack_format {
+        pack.insert(String::from("pack_format"), json!(format));
+    }
+    if let Some((min, max)) = options.supported_formats {
+        pack.insert(String::from("supported_formats"), json!([min, max]));
+    }
+
+    if !pack.contains_key("pack_format") {
+        return Err(ErrorKind::from("no pack_format given for the generated pack.mcmeta; please set --pack-format or --target-version"));
+    }
+    if !pack.contains_key("description") {
+        pack.insert(String::from("description"), json!(""));
+    }
+
+    let bytes = serde_json::to_vec_pretty(&Value::Object(root))?;
+    return Ok(bytes);
+}
+
+// 3x5 glyphs, one row per u8 with the low three bits used, left-most pixel in bit 2
+fn badge_glyph(c: char) -> Option<[u8; 5]> {
+    match c {
+        'x' => Some([0b000, 0b101, 0b010, 0b101, 0b000]),
+        '0' => Some([0b111, 0b101, 0b101, 0b101, 0b111]),
+        '1' => Some([0b010, 0b110, 0b010, 0b010, 0b111]),
+        '2' => Some([0b111, 0b001, 0b111, 0b100, 0b111]),
+        '3' => Some([0b111, 0b001, 0b011, 0b001, 0b111]),
+        '4' => Some([0b101, 0b101, 0b111, 0b001, 0b001]),
+        '5' => Some([0b111, 0b100, 0b111, 0b001, 0b111]),
+        '6' => Some([0b111, 0b100, 0b111, 0b101, 0b111]),
+        '7' => Some([0b111, 0b001, 0b010, 0b010, 0b010]),
+        '8' => Some([0b111, 0b101, 0b111, 0b101, 0b111]),
+        '9' => Some([0b111, 0b101, 0b111, 0b001, 0b111]),
+        _ => None
+    }
+}
+
+/// Draws a badge such as "x8" into the bottom-right corner of the pack icon.
+pub(crate) fn draw_scale_badge(img: &mut RgbaImage, scale: u32) {
+    let text = format!("x{scale}");
+    let glyphs: Vec<[u8; 5]> = text.chars().filter_map(badge_glyph).collect();
+
+    // Size glyph pixels so the badge covers roughly a third of the icon's width
+    let text_cells = glyphs.len() as u32 * 4 - 1;
+    let cell = std::cmp::max(1, img.width() / ((text_cells + 2) * 3));
+    let badge_width = (text_cells + 2) * cell;
+    let badge_height = 7 * cell;
+    if badge_width > img.width() || badge_height > img.height() {
+        return;
+    }
+
+    let left = img.width() - badge_width;
+    let top = img.height() - badge_height;
+    for y in top..img.height() {
+        for x in left..img.width() {
+            img.put_pixel(x, y, Rgba([32, 32, 32, 224]));
+        }
+    }
+
+    for (i, glyph) in glyphs.iter().enumerate() {
+        let glyph_left = left + cell + i as u32 * 4 * cell;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for y in 0..cell {
+                    for x in 0..cell {
+                        img.put_pixel(glyph_left + column * cell + x, top + cell + row as u32 * cell + y, Rgba([255, 255, 255, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
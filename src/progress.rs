@@ -0,0 +1,176 @@
+use std::{io::{IsTerminal, Write}, sync::{atomic::{AtomicU64, Ordering}, Mutex}, time::{Duration, Instant}};
+
+use crate::error::Stage;
+
+const STAGES: [Stage; 6] = [Stage::Read, Stage::Decode, Stage::Upscale, Stage::Metadata, Stage::Encode, Stage::Write];
+
+// How often the progress line is redrawn on a terminal
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Tracks how far through the pack processing is, and draws it as a progress line on a terminal,
+/// or as a log line every 10% otherwise. Also totals the time spent in each stage and bytes written.
+pub(crate) struct Progress {
+    total: usize,
+    interactive: bool,
+    started: Instant,
+    state: Mutex<ProgressState>,
+    stage_nanos: [AtomicU64; STAGES.len()],
+    bytes_written: AtomicU64,
+}
+
+struct ProgressState {
+    done: usize,
+    category: &'static str,
+    last_drawn: Option<Instant>,
+    next_logged_percent: usize,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize) -> Progress {
+        return Progress {
+            total,
+            interactive: std::io::stdout().is_terminal(),
+            started: Instant::now(),
+            state: Mutex::new(ProgressState {
+                done: 0,
+                category: "",
+                last_drawn: None,
+                next_logged_percent: 10,
+            }),
+            stage_nanos: Default::default(),
+            bytes_written: AtomicU64::new(0),
+        };
+    }
+
+    /// Runs `f`, adding the time it takes to `stage`'s total.
+    pub(crate) fn time<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        if let Some(i) = STAGES.iter().position(|s| *s == stage) {
+            self.stage_nanos[i].fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+        return result;
+    }
+
+    pub(crate) fn add_bytes_written(&self, bytes: usize) {
+        self.bytes_written.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Sets the kind of resource now being processed, shown on the progress line.
+    pub(crate) fn set_category(&self, category: &'static str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.category = category;
+        }
+    }
+
+    /// Counts one more resource as done, whether or not it succeeded.
+    pub(crate) fn finish_one(&self) {
+        let mut state = match self.state.lock() {
+            Ok(s) => s,
+            Err(_e) => return
+        };
+        state.done += 1;
+
+        if self.interactive {
+            let due = state.last_drawn.is_none_or(|t| t.elapsed() >= REDRAW_INTERVAL);
+            if due || state.done == self.total {
+                state.last_drawn = Some(Instant::now());
+                self.draw(&state);
+            }
+        } else if state.done * 100 >= state.next_logged_percent * self.total {
+            while state.done * 100 >= state.next_logged_percent * self.total && state.next_logged_percent <= 100 {
+                state.next_logged_percent += 10;
+            }
+            println!("{0}", self.status_line(&state));
+        }
+    }
+
+    /// Prints a message without it getting mixed up with the progress line.
+    pub(crate) fn println(&self, line: &str) {
+        let state = match self.state.lock() {
+            Ok(s) => s,
+            Err(_e) => {
+                println!("{line}");
+                return;
+            }
+        };
+
+        if self.interactive {
+            print!("\r\x1b[2K");
+            println!("{line}");
+            if state.last_drawn.is_some() {
+                self.draw(&state);
+            }
+        } else {
+            println!("{line}");
+        }
+    }
+
+    /// Moves past the progress line, so whatever is printed next starts on a line of its own.
+    pub(crate) fn finish(&self) {
+        if let Ok(state) = self.state.lock() {
+            if self.interactive && state.last_drawn.is_some() {
+                println!();
+            }
+        }
+    }
+
+    fn draw(&self, state: &ProgressState) {
+        print!("\r\x1b[2K{0}", self.status_line(state));
+        let _ = std::io::stdout().flush();
+    }
+
+    fn status_line(&self, state: &ProgressState) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { state.done as f64 / elapsed } else { 0.0 };
+        let eta = if rate > 0.0 {
+            format_duration(Duration::from_secs_f64((self.total - state.done) as f64 / rate))
+        } else {
+            String::from("--:--")
+        };
+        let percent = (state.done * 100).checked_div(self.total).unwrap_or(100);
+
+        return format!("{0}/{1} ({percent}%) | {2} | {rate:.1} files/s | ETA {eta}", state.done, self.total, state.category);
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        return self.started.elapsed();
+    }
+
+    pub(crate) fn bytes_written(&self) -> u64 {
+        return self.bytes_written.load(Ordering::Relaxed);
+    }
+
+    /// Time spent in each stage, summed across all workers.
+    pub(crate) fn stage_times(&self) -> Vec<(Stage, Duration)> {
+        return STAGES.iter()
+            .zip(self.stage_nanos.iter())
+            .map(|(s, n)| (*s, Duration::from_nanos(n.load(Ordering::Relaxed))))
+            .collect();
+    }
+}
+
+/// `m:ss`, or `h:mm:ss` for an hour or more.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        return format!("{0}:{1:02}:{2:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+    }
+    return format!("{0}:{1:02}", seconds / 60, seconds % 60);
+}
+
+/// Bytes in the largest unit that keeps the number at 1 or more, e.g. "12.3 MiB".
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        return format!("{bytes} B");
+    }
+    return format!("{value:.1} {0}", units[unit]);
+}
@@ -0,0 +1,146 @@
+use std::{path::{Path, PathBuf}, time::Duration};
+use almostdefault::{BlockBorder, ColourDistance, ColourSpace, MaskShape, MedianMode, Options, TextureKind};
+use serde::Serialize;
+
+use crate::{error::{Error, Stage}, fs_operations::write_atomically, resource_operations::Outcome};
+
+/// A machine-readable record of a run, written with `--report`. Resources are listed by input path, so reports
+/// from two runs can be diffed to see what was classified or processed differently.
+#[derive(Serialize)]
+pub(crate) struct Report {
+    input: String,
+    output: String,
+    resources: Vec<ReportEntry>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ReportEntry {
+    input: String,
+    resource_type: &'static str,
+    skip_reason: Option<&'static str>,
+    pipeline: Option<&'static str>,
+    parameters: Option<ReportParameters>,
+    output: Option<String>,
+    input_size: Option<[u32; 2]>,
+    output_size: Option<[u32; 2]>,
+    /// Left as it was from an earlier run, as nothing it's made from changed
+    cached: bool,
+    millis: Option<f64>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ReportParameters {
+    scale: u32,
+    median: u32,
+    median_mode: &'static str,
+    colour_space: &'static str,
+    alpha_aware: bool,
+    mask: String,
+    block_border: &'static str,
+}
+
+impl Report {
+    pub(crate) fn new(input: &Path, output: &Path) -> Report {
+        return Report {
+            input: input.to_string_lossy().into_owned(),
+            output: output.to_string_lossy().into_owned(),
+            resources: Vec::new(),
+        };
+    }
+
+    /// Records a resource that was processed, or failed to be. `pipeline` is left out for resources that weren't upscaled.
+    pub(crate) fn add(&mut self, resource: &Path, resource_type: &'static str, pipeline: Option<(TextureKind, &Options)>,
+                      output: PathBuf, result: Result<Outcome, &Error>, time: Option<Duration>) {
+        let (outcome, error) = match result {
+            Ok(o) => (o, None),
+            Err(e) => (Outcome::default(), Some(e))
+        };
+
+        self.resources.push(ReportEntry {
+            input: resource.to_string_lossy().into_owned(),
+            resource_type,
+            skip_reason: None,
+            pipeline: pipeline.map(|(k, _o)| kind_name(k)),
+            parameters: pipeline.map(|(_k, o)| ReportParameters::new(o)),
+            output: if error.is_none() { Some(output.to_string_lossy().into_owned()) } else { None },
+            input_size: outcome.input_size.map(|(w, h)| [w, h]),
+            output_size: outcome.output_size.map(|(w, h)| [w, h]),
+            cached: outcome.cached,
+            millis: time.map(|t| t.as_secs_f64() * 1000.0),
+            error: error.map(|e| format!("{0} failed: {1}", e.stage, e.kind)),
+        });
+    }
+
+    /// Records a resource left out of the output, by a config rule or the source filters.
+    pub(crate) fn add_skipped(&mut self, resource: &Path, reason: &'static str) {
+        self.resources.push(ReportEntry {
+            input: resource.to_string_lossy().into_owned(),
+            resource_type: "skip",
+            skip_reason: Some(reason),
+            pipeline: None,
+            parameters: None,
+            output: None,
+            input_size: None,
+            output_size: None,
+            cached: false,
+            millis: None,
+            error: None,
+        });
+    }
+
+    pub(crate) fn write(mut self, path: &Path) -> Result<(), Error> {
+        self.resources.sort_by(|a, b| a.input.cmp(&b.input));
+
+        let bytes = match serde_json::to_vec_pretty(&self) {
+            Ok(b) => b,
+            Err(e) => return Err(Error::new(path, Stage::Encode, e))
+        };
+        match write_atomically(path, &bytes) {
+            Ok(()) => return Ok(()),
+            Err(e) => return Err(Error::new(path, Stage::Write, e))
+        };
+    }
+}
+
+impl ReportParameters {
+    fn new(options: &Options) -> ReportParameters {
+        return ReportParameters {
+            scale: options.scale(),
+            median: options.median(),
+            median_mode: match options.median_mode() {
+                MedianMode::Classic => "classic",
+                MedianMode::Vector(ColourDistance::Rgb) => "vector:rgb",
+                MedianMode::Vector(ColourDistance::Oklab) => "vector:oklab"
+            },
+            colour_space: match options.colour_space() {
+                ColourSpace::Srgb => "srgb",
+                ColourSpace::Linear => "linear",
+                ColourSpace::Oklab => "oklab"
+            },
+            alpha_aware: options.alpha_aware(),
+            mask: match options.mask_shape() {
+                MaskShape::Circle => String::from("circle"),
+                MaskShape::Diamond => String::from("diamond"),
+                MaskShape::Superellipse(n) => format!("superellipse:{n}"),
+                MaskShape::RoundedSquare(r) => format!("rounded:{r}"),
+                MaskShape::Custom(_) => String::from("custom")
+            },
+            block_border: match options.block_border() {
+                BlockBorder::Auto => "auto",
+                BlockBorder::Wrap => "wrap",
+                BlockBorder::Mean => "mean"
+            },
+        };
+    }
+}
+
+fn kind_name(kind: TextureKind) -> &'static str {
+    match kind {
+        TextureKind::Block => return "block",
+        TextureKind::Item => return "item",
+        TextureKind::Entity => return "entity",
+        TextureKind::Smooth => return "smooth",
+        TextureKind::Nearest => return "nearest"
+    }
+}
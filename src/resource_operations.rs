@@ -1,178 +1,479 @@
-use std::{collections::HashMap, path::PathBuf};
-use image::{ImageBuffer, Rgba, RgbaImage};
-use tokio::fs::{self, File};
+use std::{collections::HashMap, io::Cursor, path::{Path, PathBuf}};
+use image::{imageops, ImageFormat, ImageReader, RgbaImage};
+
+use almostdefault::{upscale, BlockBorder, Options, TextureKind};
+
+use crate::{asset_layout::{parse_texture_path, TextureCategory, TexturePath}, cache::{Cache, CacheEntry, CacheKey}, config::{Config, RuleAction}, error::{Error, Stage}, fs_operations::{ResourceSink, ResourceSource}, pack_metadata::{build_pack_mcmeta, draw_scale_badge, PackMetadataOptions}, progress::Progress, target_resolution::{describe_scale, is_tile_texture, tile_size, TargetResolution}, texture_metadata::{frame_layout, read_animation, scale_texture_mcmeta}};
+
+/// Everything a worker needs to process any resource in the pack.
+pub(crate) struct ProcessingContext {
+    pub(crate) source: ResourceSource,
+    pub(crate) sink: ResourceSink,
+    pub(crate) upscaling_parameters: Options,
+    pub(crate) config: Config,
+    pub(crate) pack_metadata_options: PackMetadataOptions,
+    pub(crate) icon_badge: bool,
+    pub(crate) progress: Progress,
+    pub(crate) cache: Cache,
+    /// With `--target-resolution`, the tile size to scale each texture up to, in place of a fixed scale
+    pub(crate) target: Option<TargetResolution>,
+}
+
+/// What came of processing a resource.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Outcome {
+    /// The texture's size before and after, for textures
+    pub(crate) input_size: Option<(u32, u32)>,
+    pub(crate) output_size: Option<(u32, u32)>,
+    /// The output from an earlier run was kept, as nothing it's made from changed
+    pub(crate) cached: bool,
+    /// Copied as it was rather than upscaled, being at `--target-resolution` already
+    pub(crate) kept: bool,
+}
 
-use crate::{image_manipulation::{circular_filter, get_mean_colour, median_upscale, median_upscale_with_corner_pass}, UpscalingParameters};
+impl ProcessingContext {
+    pub(crate) fn options_for(&self, resource: &Path) -> &Options {
+        return options_for(resource, &self.config, &self.upscaling_parameters);
+    }
+}
 
+/// The options from the first config rule matching the resource, or `defaults` (the command-line ones).
+/// A texture's `.png.mcmeta` goes by its texture, so frame sizes are scaled to match.
+pub(crate) fn options_for<'a>(resource: &Path, config: &'a Config, defaults: &'a Options) -> &'a Options {
+    let texture = texture_for_mcmeta(resource);
+    let resource = match &texture {
+        Some(t) => t.as_path(),
+        None => resource
+    };
+
+    match config.action_for(resource) {
+        Some(RuleAction::Upscale(_kind, options)) => return options,
+        _ => return defaults
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum ResourceType {
     NonImage,
     Item,
     Entity,
     Block,
+    AnimatedBlock,
+    Smooth,
+    Nearest,
+    TextureMetadata,
+    PackMetadata,
+    PackIcon,
+}
+
+impl ResourceType {
+    /// How the type is shown in progress and summary output.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ResourceType::NonImage => return "copy",
+            ResourceType::Item => return "item",
+            ResourceType::Entity => return "entity",
+            ResourceType::Block => return "block",
+            ResourceType::AnimatedBlock => return "animated block",
+            ResourceType::Smooth => return "smooth",
+            ResourceType::Nearest => return "nearest",
+            ResourceType::TextureMetadata => return "texture metadata",
+            ResourceType::PackMetadata => return "pack metadata",
+            ResourceType::PackIcon => return "pack icon"
+        }
+    }
+
+    /// The pipeline textures of this type are upscaled through, if they're upscaled at all.
+    pub(crate) fn pipeline(&self) -> Option<TextureKind> {
+        match self {
+            ResourceType::Block | ResourceType::AnimatedBlock | ResourceType::PackIcon => return Some(TextureKind::Block),
+            ResourceType::Item => return Some(TextureKind::Item),
+            ResourceType::Entity => return Some(TextureKind::Entity),
+            ResourceType::Smooth => return Some(TextureKind::Smooth),
+            ResourceType::Nearest => return Some(TextureKind::Nearest),
+            ResourceType::NonImage | ResourceType::TextureMetadata | ResourceType::PackMetadata => return None
+        }
+    }
 }
 
-pub(crate) fn determine_resource_type(resources: &Vec<PathBuf>) -> Result<HashMap<PathBuf, ResourceType>, &'static str> {
+pub(crate) fn determine_resource_type(resources: &Vec<PathBuf>, config: &Config) -> HashMap<PathBuf, ResourceType> {
     let mut type_map = HashMap::new();
-    let mut filename;
-    let mut path_as_string;
 
     for r in resources {
-        filename = match r.extension() {
-            Some(f) => f,
-            None => return Err("Unable to resolve file extension.")
-        };
-        path_as_string = r.to_string_lossy();
-
-        if filename != "png" || path_as_string.contains("colormap") {
+        if r.as_os_str() == "pack.mcmeta" {
+            type_map.insert(r.to_owned(), ResourceType::PackMetadata);
+        } else if r.as_os_str() == "pack.png" {
+            type_map.insert(r.to_owned(), ResourceType::PackIcon);
+        } else if r.extension().is_none_or(|e| e != "png") {
+            // Including files without an extension, such as LICENSE or a macOS .DS_Store
             type_map.insert(r.to_owned(), ResourceType::NonImage);
+        } else if let Some(action) = config.action_for(r) {
+            let resource_type = match action {
+                RuleAction::Skip | RuleAction::Copy => ResourceType::NonImage,
+                RuleAction::Upscale(kind, _options) => kind_resource_type(*kind)
+            };
+            type_map.insert(r.to_owned(), resource_type);
         } else {
-            if path_as_string.contains("item") {
-                type_map.insert(r.to_owned(), ResourceType::Item);
-            } else if path_as_string.contains("entity") {
-                type_map.insert(r.to_owned(), ResourceType::Entity);
-            } else {
-                type_map.insert(r.to_owned(), ResourceType::Block);
-            }
+            // PNGs outside the usual layout (e.g. OptiFine's) are most often block-like, so get the block treatment
+            let resource_type = match parse_texture_path(r) {
+                Some(t) => texture_resource_type(&t),
+                None => ResourceType::Block
+            };
+            type_map.insert(r.to_owned(), resource_type);
+        }
+    }
+
+    // Textures with a .png.mcmeta may be animated, and their metadata has to follow them when they're upscaled
+    for r in resources {
+        let texture = match texture_for_mcmeta(r) {
+            Some(t) => t,
+            None => continue
+        };
+        match type_map.get(&texture) {
+            Some(ResourceType::Block) => {
+                type_map.insert(texture, ResourceType::AnimatedBlock);
+                type_map.insert(r.to_owned(), ResourceType::TextureMetadata);
+            },
+            Some(ResourceType::Item) | Some(ResourceType::Entity) | Some(ResourceType::Smooth) | Some(ResourceType::Nearest) => {
+                type_map.insert(r.to_owned(), ResourceType::TextureMetadata);
+            },
+            _ => ()
         }
     }
 
-    return Ok(type_map);
+    return type_map;
 }
 
-pub(crate) async fn copy_resource(read_root: &PathBuf, write_root: &PathBuf, resource: PathBuf) -> Result<(), &'static str> {
-    match fs::copy(read_root.join(&resource), write_root.join(resource)).await {
-        Ok(_u) => return Ok(()),
-        Err(_e) => return Err("Error: Unable to copy resource.")
-    };
+/// Picks how a texture is processed from what the game uses it for.
+fn texture_resource_type(texture: &TexturePath) -> ResourceType {
+    match texture.category {
+        TextureCategory::Block | TextureCategory::Misc | TextureCategory::Other => return ResourceType::Block,
+        TextureCategory::Item | TextureCategory::Particle | TextureCategory::MobEffect | TextureCategory::Painting
+            | TextureCategory::Environment | TextureCategory::Map => return ResourceType::Item,
+        TextureCategory::Entity | TextureCategory::Models => return ResourceType::Entity,
+        // GUI sprites are nine-sliced and font glyph widths are measured from their pixels, so neither can be smoothed
+        TextureCategory::Gui | TextureCategory::Font => return ResourceType::Nearest,
+        // Colour lookup tables, read by position rather than drawn
+        TextureCategory::Colormap | TextureCategory::Effect => return ResourceType::NonImage,
+        TextureCategory::Trims => {
+            if texture.name.starts_with("color_palettes") {
+                return ResourceType::NonImage;
+            } else if texture.name.starts_with("items") {
+                return ResourceType::Item;
+            }
+            return ResourceType::Entity;
+        }
+    }
 }
 
-pub(crate) async fn process_block_resource(resource: PathBuf, read_root: &PathBuf, write_root: &PathBuf, upscaling_parameters: &UpscalingParameters) -> Result<(), &'static str> {
-    let source_img = match image::open(read_root.join(&resource)) {
-        Ok(i) => RgbaImage::from(i),
-        Err(_e) => return Err("Error: Unable to read image into buffer. (block)")
-    };
+fn kind_resource_type(kind: TextureKind) -> ResourceType {
+    match kind {
+        TextureKind::Block => return ResourceType::Block,
+        TextureKind::Item => return ResourceType::Item,
+        TextureKind::Entity => return ResourceType::Entity,
+        TextureKind::Smooth => return ResourceType::Smooth,
+        TextureKind::Nearest => return ResourceType::Nearest
+    }
+}
+
+/// `block/water_still.png.mcmeta` -> `block/water_still.png`
+pub(crate) fn texture_for_mcmeta(resource: &Path) -> Option<PathBuf> {
+    let file_name = resource.file_name()?.to_str()?;
+    let texture_name = file_name.strip_suffix(".mcmeta")?;
+    if !texture_name.ends_with(".png") {
+        return None;
+    }
+    return Some(resource.with_file_name(texture_name));
+}
 
-    let mut intermediate_img: ImageBuffer<Rgba<u8>, Vec<u8>> = RgbaImage::new(source_img.width() + 2, source_img.height() + 2);
-    let mut corner_colours: Vec<&Rgba<u8>> = Vec::new();
-    let mut current_colour: &Rgba<u8>;
+/// `block/water_still.png` -> `block/water_still.png.mcmeta`
+pub(crate) fn mcmeta_for_texture(resource: &Path) -> PathBuf {
+    let mut mcmeta = resource.as_os_str().to_owned();
+    mcmeta.push(".mcmeta");
+    return PathBuf::from(mcmeta);
+}
 
-    for y in 1..source_img.height() + 1 {
-        for x in 1..source_img.width() + 1 {
-            current_colour = source_img.get_pixel(x-1, y-1);
-            intermediate_img.put_pixel(x, y, current_colour.to_owned());
+/// Runs whichever process suits the resource's type, unless its output from an earlier run is still up to date.
+/// This is CPU-bound, so should be run on a blocking thread.
+pub(crate) fn process_resource(resource: PathBuf, resource_type: ResourceType, context: &ProcessingContext) -> Result<Outcome, Error> {
+    if !context.cache.is_enabled() {
+        return process_uncached(resource, resource_type, context);
+    }
 
-            if y == 1 || y == source_img.height() || x == 1 || x == source_img.height() {
-                corner_colours.push(current_colour);
-            }
-        }
+    let key = cache_key(&resource, resource_type, context)?;
+    if let Some(entry) = context.cache.lookup(&resource, &key) {
+        let outcome = Outcome { input_size: entry.input_size, output_size: entry.output_size, cached: true, kept: false };
+        context.cache.record(&resource, Some(entry));
+        return Ok(outcome);
     }
 
-    let mean_colour: Rgba<u8> = get_mean_colour(corner_colours).await;
+    let result = process_uncached(resource.clone(), resource_type, context);
+    let entry = result.as_ref().ok().map(|o| CacheEntry::new(key, o.input_size, o.output_size));
+    context.cache.record(&resource, entry);
+    return result;
+}
+
+/// Hashes everything the resource's output depends on: its own bytes, those of its texture or `.png.mcmeta`
+/// where one is read alongside it, and the settings it's processed with.
+fn cache_key(resource: &PathBuf, resource_type: ResourceType, context: &ProcessingContext) -> Result<CacheKey, Error> {
+    let progress = &context.progress;
+    let bytes = read_resource(&context.source, resource, progress)?;
+    let mcmeta = mcmeta_for_texture(resource);
+    let companion = match resource_type {
+        ResourceType::AnimatedBlock => Some(read_resource(&context.source, &mcmeta, progress)?),
+        ResourceType::TextureMetadata => match texture_for_mcmeta(resource) {
+            Some(t) => Some(read_resource(&context.source, &t, progress)?),
+            None => None
+        },
+        // Under --target-resolution any animated texture's scale comes from its frame size
+        ResourceType::Item | ResourceType::Entity | ResourceType::Smooth | ResourceType::Nearest
+            if context.target.is_some() && context.source.contains(&mcmeta) => Some(read_resource(&context.source, &mcmeta, progress)?),
+        _ => None
+    };
 
-    for y in 0..intermediate_img.height() {
-        intermediate_img.put_pixel(0, y, mean_colour);
-        intermediate_img.put_pixel(intermediate_img.width() - 1, y, mean_colour);
+    let mut parameters = format!("{0}|{1}|{2:?}", env!("CARGO_PKG_VERSION"), resource_type.name(), context.options_for(resource));
+    match resource_type {
+        ResourceType::PackMetadata => parameters.push_str(&format!("|{0:?}", context.pack_metadata_options)),
+        ResourceType::PackIcon => parameters.push_str(&format!("|{0}", context.icon_badge)),
+        _ => ()
+    }
+    if let Some(target) = &context.target {
+        parameters.push_str(&format!("|target {0}|base {1}", target.resolution, target.base_tile(resource)));
     }
 
-    for x in 0..intermediate_img.width() {
-        intermediate_img.put_pixel(x, 0, mean_colour);
-        intermediate_img.put_pixel(x, intermediate_img.height() - 1, mean_colour);
+    match &companion {
+        Some(c) => return Ok(CacheKey::new(&[&bytes, c], &parameters)),
+        None => return Ok(CacheKey::new(&[&bytes], &parameters))
     }
+}
 
-    let mut upscaled_img = match median_upscale(&intermediate_img, &upscaling_parameters).await {
-        Ok(i) => i,
-        Err(e) => return Err(e)
+fn process_uncached(resource: PathBuf, resource_type: ResourceType, context: &ProcessingContext) -> Result<Outcome, Error> {
+    let source = &context.source;
+    let sink = &context.sink;
+    let progress = &context.progress;
+
+    // The pack icon isn't in a namespace, so follows the pack's own tile size and its badge matches the textures
+    let target_options = match (&context.target, resource_type) {
+        (Some(target), ResourceType::TextureMetadata) => match texture_for_mcmeta(&resource) {
+            Some(texture) => Some(options_for_target(&texture, context.options_for(&resource), target, false, context)?),
+            None => None
+        },
+        (Some(_target), ResourceType::NonImage | ResourceType::PackMetadata) => None,
+        (Some(target), _) => Some(options_for_target(&resource, context.options_for(&resource), target, true, context)?),
+        (None, _) => None
     };
-
-    upscaled_img = match circular_filter(&intermediate_img, upscaled_img, upscaling_parameters).await {
-        Ok(i) => i,
-        Err(e) => return Err(e)
+    let upscaling_parameters = match &target_options {
+        Some(TargetOptions::Upscale(o)) => o,
+        // Already at or above the target, so left as it is along with its .png.mcmeta
+        Some(TargetOptions::Keep(size)) => {
+            copy_resource(source, sink, resource, progress)?;
+            let size = if matches!(resource_type, ResourceType::TextureMetadata) { None } else { Some(*size) };
+            return Ok(Outcome { input_size: size, output_size: size, cached: false, kept: true });
+        },
+        None => context.options_for(&resource)
     };
 
-    let mut trimmed_upscaled_img = RgbaImage::new(source_img.width() * upscaling_parameters.scale as u32, source_img.height() * upscaling_parameters.scale as u32);
-
-    for y in 0..trimmed_upscaled_img.height() {
-        for x in 0..trimmed_upscaled_img.width() {
-            trimmed_upscaled_img.put_pixel(x, y, *upscaled_img.get_pixel(x + upscaling_parameters.scale as u32, y + upscaling_parameters.scale as u32))
-        }
+    match resource_type {
+        ResourceType::NonImage => return copy_resource(source, sink, resource, progress),
+        ResourceType::Block => return process_texture_resource(resource, source, sink, upscaling_parameters, TextureKind::Block, progress),
+        ResourceType::AnimatedBlock => return process_animated_block_resource(resource, source, sink, upscaling_parameters, progress),
+        ResourceType::Item => return process_texture_resource(resource, source, sink, upscaling_parameters, TextureKind::Item, progress),
+        ResourceType::Entity => return process_texture_resource(resource, source, sink, upscaling_parameters, TextureKind::Entity, progress),
+        ResourceType::Smooth => return process_texture_resource(resource, source, sink, upscaling_parameters, TextureKind::Smooth, progress),
+        ResourceType::Nearest => return process_texture_resource(resource, source, sink, upscaling_parameters, TextureKind::Nearest, progress),
+        ResourceType::TextureMetadata => return process_texture_metadata(resource, source, sink, upscaling_parameters, progress),
+        ResourceType::PackMetadata => return process_pack_metadata(source, sink, resource, &context.pack_metadata_options, progress),
+        ResourceType::PackIcon => return process_pack_icon(resource, source, sink, upscaling_parameters, context.icon_badge, progress)
     }
+}
+
+enum TargetOptions {
+    /// The resource's options, at the scale that brings it to the target resolution
+    Upscale(Options),
+    /// The texture, of this size, is already at or above the target resolution
+    Keep((u32, u32)),
+}
 
-    let file = write_root.join(&resource);
-    match File::create(&file).await {
-        Ok(f) => f,
-        Err(_e) => {
-            return Err("Error: Failed to create image file.")
+/// Works out the scale for `texture`: from the size of one of its tiles for block and item textures, or from its
+/// namespace's tile size for sheets. Targets that aren't a whole multiple of the tile are rounded down, with a warning.
+fn options_for_target(texture: &PathBuf, options: &Options, target: &TargetResolution, warn: bool, context: &ProcessingContext) -> Result<TargetOptions, Error> {
+    let progress = &context.progress;
+    let ((width, height), tile) = progress.time(Stage::Decode, || tile_size(texture, &context.source))?;
+
+    let scale = if is_tile_texture(texture) {
+        let resolution = target.resolution;
+        if warn && tile > 0 && tile < resolution && !resolution.is_multiple_of(tile) {
+            progress.println(&format!("Warning: {0}: {tile}px tiles don't scale evenly to {resolution}px; {1}", texture.display(), describe_scale(tile, resolution)));
         }
+        target.scale_for(tile)
+    } else {
+        // Sheets of parts go by the pixel size of their namespace's tiles, which was warned about when it was measured
+        target.sheet_scale(texture)
+    };
+    let scale = match scale {
+        Some(s) => s,
+        None => return Ok(TargetOptions::Keep((width, height)))
     };
 
-    match trimmed_upscaled_img.save(&file) {
-        Ok(x) => x,
-        Err(_e) => return Err("Error: Failed to write image contents to file.")
+    match options.to_builder().scale(scale).build() {
+        Ok(o) => return Ok(TargetOptions::Upscale(o)),
+        Err(e) => return Err(Error::new(texture, Stage::Upscale, e))
     };
+}
+
+pub(crate) fn copy_resource(source: &ResourceSource, sink: &ResourceSink, resource: PathBuf, progress: &Progress) -> Result<Outcome, Error> {
+    let bytes = read_resource(source, &resource, progress)?;
+    write_resource(sink, &resource, &bytes, progress)?;
+    return Ok(Outcome::default());
+}
 
+fn read_resource(source: &ResourceSource, resource: &PathBuf, progress: &Progress) -> Result<Vec<u8>, Error> {
+    return progress.time(Stage::Read, || source.read(resource));
+}
+
+fn write_resource(sink: &ResourceSink, resource: &PathBuf, bytes: &[u8], progress: &Progress) -> Result<(), Error> {
+    progress.time(Stage::Write, || sink.write(resource, bytes))?;
+    progress.add_bytes_written(bytes.len());
     return Ok(());
 }
 
-pub(crate) async fn process_item_resource(resource: PathBuf, read_root: &PathBuf, write_root: &PathBuf, upscaling_parameters: &UpscalingParameters) -> Result<(), &'static str> {
-    let source_img = match image::open(read_root.join(&resource)) {
-        Ok(i) => RgbaImage::from(i),
-        Err(_e) => return Err("Error: Unable to read image into buffer. (item)")
+fn read_image(source: &ResourceSource, resource: &PathBuf, progress: &Progress) -> Result<RgbaImage, Error> {
+    let bytes = read_resource(source, resource, progress)?;
+
+    match progress.time(Stage::Decode, || image::load_from_memory_with_format(&bytes, ImageFormat::Png)) {
+        Ok(i) => return Ok(RgbaImage::from(i)),
+        Err(e) => return Err(Error::new(resource, Stage::Decode, e))
     };
+}
+
+/// Writes the upscaled `img`, returning its size along with `source_img`'s.
+fn write_image(sink: &ResourceSink, resource: &PathBuf, source_img: &RgbaImage, img: &RgbaImage, progress: &Progress) -> Result<Outcome, Error> {
+    let mut bytes = Vec::new();
+    if let Err(e) = progress.time(Stage::Encode, || img.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)) {
+        return Err(Error::new(resource, Stage::Encode, e));
+    }
 
-    let mut upscaled_img = match median_upscale(&source_img, &upscaling_parameters).await {
-        Ok(i) => i,
-        Err(e) => return Err(e)
+    write_resource(sink, resource, &bytes, progress)?;
+    return Ok(Outcome { input_size: Some(source_img.dimensions()), output_size: Some(img.dimensions()), cached: false, kept: false });
+}
+
+fn upscale_image(resource: &PathBuf, img: &RgbaImage, upscaling_parameters: &Options, kind: TextureKind, progress: &Progress) -> Result<RgbaImage, Error> {
+    match progress.time(Stage::Upscale, || upscale(img, upscaling_parameters, kind)) {
+        Ok(i) => return Ok(i),
+        Err(e) => return Err(Error::new(resource, Stage::Upscale, e))
     };
+}
+
+/// Writes the output pack.mcmeta, rewritten with `options` if any were given or copied as-is otherwise.
+pub(crate) fn process_pack_metadata(source: &ResourceSource, sink: &ResourceSink, resource: PathBuf, options: &PackMetadataOptions, progress: &Progress) -> Result<Outcome, Error> {
+    if options.is_empty() {
+        return copy_resource(source, sink, resource, progress);
+    }
 
-    upscaled_img = match circular_filter(&source_img, upscaled_img, upscaling_parameters).await {
-        Ok(i) => i,
-        Err(e) => return Err(e)
+    let existing = read_resource(source, &resource, progress)?;
+    let bytes = match progress.time(Stage::Metadata, || build_pack_mcmeta(Some(&existing), options)) {
+        Ok(b) => b,
+        Err(e) => return Err(Error::new(&resource, Stage::Metadata, e))
     };
+    write_resource(sink, &resource, &bytes, progress)?;
+    return Ok(Outcome::default());
+}
 
-    let file = write_root.join(&resource);
-    match File::create(&file).await {
-        Ok(f) => f,
-        Err(_e) => {
-            return Err("Error: Failed to create image file.")
-        }
+/// Writes a fresh pack.mcmeta for sources that don't have one.
+pub(crate) fn create_pack_metadata(sink: &ResourceSink, options: &PackMetadataOptions, progress: &Progress) -> Result<(), Error> {
+    let resource = PathBuf::from("pack.mcmeta");
+    let bytes = match progress.time(Stage::Metadata, || build_pack_mcmeta(None, options)) {
+        Ok(b) => b,
+        Err(e) => return Err(Error::new(&resource, Stage::Metadata, e))
     };
+    return write_resource(sink, &resource, &bytes, progress);
+}
 
-    match upscaled_img.save(&file) {
-        Ok(x) => x,
-        Err(_e) => return Err("Error: Failed to write image contents to file.")
+/// Upscales pack.png like a block texture, optionally marking it with the scale factor.
+pub(crate) fn process_pack_icon(resource: PathBuf, source: &ResourceSource, sink: &ResourceSink, upscaling_parameters: &Options, badge: bool, progress: &Progress) -> Result<Outcome, Error> {
+    let source_img = read_image(source, &resource, progress)?;
+
+    // The icon is never tiled, so always pad it with its mean edge colour
+    let icon_parameters = match upscaling_parameters.to_builder().block_border(BlockBorder::Mean).build() {
+        Ok(o) => o,
+        Err(e) => return Err(Error::new(&resource, Stage::Upscale, e))
     };
 
-    return Ok(());
+    let mut upscaled_img = upscale_image(&resource, &source_img, &icon_parameters, TextureKind::Block, progress)?;
+
+    if badge {
+        draw_scale_badge(&mut upscaled_img, upscaling_parameters.scale());
+    }
+
+    return write_image(sink, &resource, &source_img, &upscaled_img, progress);
 }
 
-pub(crate) async fn process_entity_resource(resource: PathBuf, read_root: &PathBuf, write_root: &PathBuf, upscaling_parameters: &UpscalingParameters) -> Result<(), &'static str> {
-    let source_img = match image::open(read_root.join(&resource)) {
-        Ok(i) => RgbaImage::from(i),
-        Err(_e) => return Err("Error: Unable to read image into buffer. (item)")
+/// Upscales a texture as a whole through the pipeline for `kind`.
+pub(crate) fn process_texture_resource(resource: PathBuf, source: &ResourceSource, sink: &ResourceSink, upscaling_parameters: &Options, kind: TextureKind, progress: &Progress) -> Result<Outcome, Error> {
+    let source_img = read_image(source, &resource, progress)?;
+    let upscaled_img = upscale_image(&resource, &source_img, upscaling_parameters, kind, progress)?;
+    return write_image(sink, &resource, &source_img, &upscaled_img, progress);
+}
+
+/// Upscales each frame of an animated block texture on its own, so smoothing never bleeds from one frame into the next.
+pub(crate) fn process_animated_block_resource(resource: PathBuf, source: &ResourceSource, sink: &ResourceSink, upscaling_parameters: &Options, progress: &Progress) -> Result<Outcome, Error> {
+    let source_img = read_image(source, &resource, progress)?;
+
+    let mcmeta = read_resource(source, &mcmeta_for_texture(&resource), progress)?;
+
+    // A .mcmeta can also just set blur / clamp, in which case this is an ordinary block
+    let animation = match read_animation(&mcmeta) {
+        Ok(Some(a)) => a,
+        Ok(None) => {
+            let upscaled_img = upscale_image(&resource, &source_img, upscaling_parameters, TextureKind::Block, progress)?;
+            return write_image(sink, &resource, &source_img, &upscaled_img, progress);
+        },
+        Err(e) => return Err(Error::new(&resource, Stage::Decode, e))
     };
 
-    let mut upscaled_img = match median_upscale_with_corner_pass(&source_img, &upscaling_parameters).await {
-        Ok(i) => i,
-        Err(e) => return Err(e)
+    let layout = match frame_layout(&animation, source_img.width(), source_img.height()) {
+        Ok(l) => l,
+        Err(e) => return Err(Error::new(&resource, Stage::Decode, e))
     };
+    let scale = upscaling_parameters.scale();
+    let mut upscaled_img = RgbaImage::new(source_img.width() * scale, source_img.height() * scale);
+
+    for row in 0..layout.rows {
+        for column in 0..layout.columns {
+            let x = column * layout.frame_width;
+            let y = row * layout.frame_height;
+            let frame = imageops::crop_imm(&source_img, x, y, layout.frame_width, layout.frame_height).to_image();
+            let upscaled_frame = upscale_image(&resource, &frame, upscaling_parameters, TextureKind::Block, progress)?;
+            imageops::replace(&mut upscaled_img, &upscaled_frame, (x * scale) as i64, (y * scale) as i64);
+        }
+    }
+
+    return write_image(sink, &resource, &source_img, &upscaled_img, progress);
+}
 
-    upscaled_img = match circular_filter(&source_img, upscaled_img, upscaling_parameters).await {
-        Ok(i) => i,
-        Err(e) => return Err(e)
+/// Scales any frame sizes in a texture's .png.mcmeta to match the upscaled texture, warning about animation data that no longer fits.
+pub(crate) fn process_texture_metadata(resource: PathBuf, source: &ResourceSource, sink: &ResourceSink, upscaling_parameters: &Options, progress: &Progress) -> Result<Outcome, Error> {
+    let texture = match texture_for_mcmeta(&resource) {
+        Some(t) => t,
+        None => return copy_resource(source, sink, resource, progress)
     };
 
-    let file = write_root.join(&resource);
-    match File::create(&file).await {
-        Ok(f) => f,
-        Err(_e) => {
-            return Err("Error: Failed to create image file.")
-        }
+    let texture_bytes = read_resource(source, &texture, progress)?;
+    let (width, height) = match progress.time(Stage::Decode, || ImageReader::with_format(Cursor::new(texture_bytes), ImageFormat::Png).into_dimensions()) {
+        Ok(d) => d,
+        Err(e) => return Err(Error::new(&texture, Stage::Decode, e))
     };
 
-    match upscaled_img.save(&file) {
-        Ok(x) => x,
-        Err(_e) => return Err("Error: Failed to write image contents to file.")
+    let mcmeta = read_resource(source, &resource, progress)?;
+
+    let (bytes, warnings) = match progress.time(Stage::Metadata, || scale_texture_mcmeta(&mcmeta, upscaling_parameters.scale(), width, height)) {
+        Ok(r) => r,
+        Err(e) => return Err(Error::new(&resource, Stage::Metadata, e))
     };
+    for w in warnings {
+        progress.println(&format!("Warning: {0}: {w}", resource.display()));
+    }
 
-    return Ok(());
-}
\ No newline at end of file
+    write_resource(sink, &resource, &bytes, progress)?;
+    return Ok(Outcome::default());
+}
@@ -0,0 +1,141 @@
+use std::{collections::{BTreeMap, HashMap}, io::Cursor, path::{Path, PathBuf}};
+use image::{ImageFormat, ImageReader};
+
+use crate::{asset_layout::{parse_texture_path, TextureCategory}, error::{Error, Stage}, fs_operations::ResourceSource, resource_operations::{mcmeta_for_texture, texture_for_mcmeta}, texture_metadata::{frame_layout, read_animation}};
+
+// Assumed for packs without any block or item textures to measure: vanilla's own resolution
+const DEFAULT_BASE_TILE: u32 = 16;
+
+/// For `--target-resolution`: the tile size to bring every texture up to, and the tile sizes the pack was drawn at.
+/// Block and item textures are one tile (or an animation strip of them) and are scaled by their own size; everything
+/// else (entities, paintings, GUI sprites, fonts...) is a sheet whose size says nothing about its pixel size, so is
+/// scaled by the usual block and item tile size of its namespace.
+pub(crate) struct TargetResolution {
+    pub(crate) resolution: u32,
+    // Each block and item texture's own tile size, as measured before the run
+    tiles: HashMap<PathBuf, u32>,
+    base_tiles: HashMap<String, u32>,
+    // For the pack icon and textures outside any namespace with blocks or items, preferring vanilla's
+    pack_base_tile: u32,
+}
+
+impl TargetResolution {
+    /// Measures the block and item textures among `resources` to find the tile size each namespace was drawn at:
+    /// the most common one, so a few higher resolution textures don't skew it. Textures that can't be read are left
+    /// out here, and fail on their own when they're processed.
+    pub(crate) fn measure<'a>(resolution: u32, resources: impl Iterator<Item = &'a PathBuf>, source: &ResourceSource) -> TargetResolution {
+        let mut tiles = HashMap::new();
+        let mut counts: BTreeMap<String, BTreeMap<u32, usize>> = BTreeMap::new();
+        for resource in resources {
+            let texture = match parse_texture_path(resource) {
+                Some(t) if is_tile_texture(resource) && resource.extension().is_some_and(|e| e == "png") => t,
+                _ => continue
+            };
+            let tile = match tile_size(resource, source) {
+                Ok((_size, t)) => t,
+                Err(_e) => continue
+            };
+            tiles.insert(resource.to_owned(), tile);
+            if tile > 0 {
+                *counts.entry(texture.namespace).or_default().entry(tile).or_default() += 1;
+            }
+        }
+
+        let base_tiles: HashMap<String, u32> = counts.iter()
+            .filter_map(|(namespace, tiles)| Some((namespace.to_owned(), most_common(tiles)?)))
+            .collect();
+        let pack_base_tile = match base_tiles.get("minecraft") {
+            Some(t) => *t,
+            None => {
+                let mut all_tiles = BTreeMap::new();
+                for (tile, count) in counts.values().flatten() {
+                    *all_tiles.entry(*tile).or_default() += count;
+                }
+                most_common(&all_tiles).unwrap_or(DEFAULT_BASE_TILE)
+            }
+        };
+
+        let target = TargetResolution { resolution, tiles, base_tiles, pack_base_tile };
+        let mut namespaces: Vec<(&str, u32)> = target.base_tiles.iter().map(|(n, t)| (n.as_str(), *t)).collect();
+        namespaces.sort();
+        for (namespace, tile) in namespaces {
+            if tile < resolution && !resolution.is_multiple_of(tile) {
+                println!("Warning: {namespace}: {tile}px tiles don't scale evenly to {resolution}px; {0}", describe_scale(tile, resolution));
+            }
+        }
+        return target;
+    }
+
+    /// The tile size the sheets in `resource`'s namespace were drawn at.
+    pub(crate) fn base_tile(&self, resource: &Path) -> u32 {
+        return parse_texture_path(resource)
+            .and_then(|t| self.base_tiles.get(&t.namespace).copied())
+            .unwrap_or(self.pack_base_tile);
+    }
+
+    /// The scale `resource` will be upscaled by, from the sizes measured before the run, or `None` if it's already
+    /// at the target and will be copied as it is. A `.png.mcmeta` goes by its texture.
+    pub(crate) fn planned_scale(&self, resource: &Path) -> Option<u32> {
+        let texture = texture_for_mcmeta(resource).unwrap_or_else(|| resource.to_owned());
+        if !is_tile_texture(&texture) {
+            return self.sheet_scale(&texture);
+        }
+        match self.tiles.get(&texture) {
+            Some(tile) => return self.scale_for(*tile),
+            // Couldn't be read, so will fail when it's processed
+            None => return self.sheet_scale(&texture)
+        }
+    }
+
+    /// The scale for the sheets in `resource`'s namespace, or `None` if they're already at the target.
+    pub(crate) fn sheet_scale(&self, resource: &Path) -> Option<u32> {
+        return self.scale_for(self.base_tile(resource));
+    }
+
+    /// The whole-number scale that brings `tile` closest to the target without going over, or `None` if that's less than 2.
+    pub(crate) fn scale_for(&self, tile: u32) -> Option<u32> {
+        let scale = self.resolution.checked_div(tile)?;
+        if scale < 2 {
+            return None;
+        }
+        return Some(scale);
+    }
+}
+
+/// Whether `texture` is a block or item texture, so one tile or an animation strip of them, rather than a sheet of parts.
+pub(crate) fn is_tile_texture(texture: &Path) -> bool {
+    return parse_texture_path(texture).is_some_and(|t| matches!(t.category, TextureCategory::Block | TextureCategory::Item));
+}
+
+/// A texture's size, and the size of one of its tiles: one frame for an animated texture, or its shorter side otherwise.
+pub(crate) fn tile_size(texture: &PathBuf, source: &ResourceSource) -> Result<((u32, u32), u32), Error> {
+    let bytes = source.read(texture)?;
+    let (width, height) = match ImageReader::with_format(Cursor::new(bytes), ImageFormat::Png).into_dimensions() {
+        Ok(d) => d,
+        Err(e) => return Err(Error::new(texture, Stage::Decode, e))
+    };
+
+    let mcmeta = mcmeta_for_texture(texture);
+    if source.contains(&mcmeta) {
+        let mcmeta_bytes = source.read(&mcmeta)?;
+        if let Ok(Some(animation)) = read_animation(&mcmeta_bytes) {
+            if let Ok(layout) = frame_layout(&animation, width, height) {
+                return Ok(((width, height), layout.frame_width.min(layout.frame_height)));
+            }
+        }
+    }
+    return Ok(((width, height), width.min(height)));
+}
+
+/// What happens to `tile`px tiles that don't scale evenly to `resolution`px, for warnings.
+pub(crate) fn describe_scale(tile: u32, resolution: u32) -> String {
+    match resolution.checked_div(tile) {
+        Some(scale) if scale >= 2 => return format!("upscaled x{scale} to {0}px", tile * scale),
+        _ => return String::from("left as it is")
+    }
+}
+
+// Ties go to the smaller tile, as more of the pack is likely drawn at the lower resolution
+fn most_common(tiles: &BTreeMap<u32, usize>) -> Option<u32> {
+    return tiles.iter().max_by_key(|(tile, count)| (**count, std::cmp::Reverse(**tile))).map(|(tile, _count)| *tile);
+}
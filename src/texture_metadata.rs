@@ -0,0 +1,139 @@
+use serde_json::{Map, Value};
+
+use crate::error::ErrorKind;
+
+/// How the frames of an animated texture are laid out in its image.
+pub(crate) struct FrameLayout {
+    pub(crate) frame_width: u32,
+    pub(crate) frame_height: u32,
+    pub(crate) columns: u32,
+    pub(crate) rows: u32,
+}
+
+impl FrameLayout {
+    pub(crate) fn frame_count(&self) -> u32 {
+        return self.columns * self.rows;
+    }
+}
+
+/// Reads the `animation` section of a texture's `.png.mcmeta`, if it has one.
+pub(crate) fn read_animation(mcmeta: &[u8]) -> Result<Option<Map<String, Value>>, ErrorKind> {
+    let root = match serde_json::from_slice::<Value>(mcmeta)? {
+        Value::Object(o) => o,
+        _ => return Err(ErrorKind::from("texture .mcmeta is not a JSON object"))
+    };
+
+    match root.get("animation") {
+        Some(Value::Object(a)) => return Ok(Some(a.to_owned())),
+        Some(_) => return Err(ErrorKind::from("texture .mcmeta has an invalid 'animation' section")),
+        None => return Ok(None)
+    }
+}
+
+/// Works out the frame grid the same way the game does: explicit `width`/`height`, or square frames the size of the shorter side.
+pub(crate) fn frame_layout(animation: &Map<String, Value>, img_width: u32, img_height: u32) -> Result<FrameLayout, ErrorKind> {
+    let width = get_dimension(animation, "width")?;
+    let height = get_dimension(animation, "height")?;
+
+    let (frame_width, frame_height) = match (width, height) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, img_height),
+        (None, Some(h)) => (img_width, h),
+        (None, None) => (img_width.min(img_height), img_width.min(img_height))
+    };
+
+    if frame_width == 0 || frame_height == 0 || frame_width > img_width || frame_height > img_height {
+        return Err(ErrorKind::from(format!("{frame_width}x{frame_height} animation frames don't fit the {img_width}x{img_height} texture")));
+    }
+
+    return Ok(FrameLayout {
+        frame_width,
+        frame_height,
+        columns: img_width / frame_width,
+        rows: img_height / frame_height,
+    });
+}
+
+/// Rewrites a texture's `.png.mcmeta` for an image upscaled by `scale`, returning it along with
+/// warnings for animation data that won't work with the upscaled texture.
+pub(crate) fn scale_texture_mcmeta(mcmeta: &[u8], scale: u32, img_width: u32, img_height: u32) -> Result<(Vec<u8>, Vec<String>), ErrorKind> {
+    let mut root = match serde_json::from_slice::<Value>(mcmeta)? {
+        Value::Object(o) => o,
+        _ => return Err(ErrorKind::from("texture .mcmeta is not a JSON object"))
+    };
+
+    let mut warnings = Vec::new();
+
+    if let Some(Value::Object(animation)) = root.get_mut("animation") {
+        let layout = frame_layout(animation, img_width, img_height)?;
+        if !img_width.is_multiple_of(layout.frame_width) || !img_height.is_multiple_of(layout.frame_height) {
+            warnings.push(format!("the {img_width}x{img_height} texture is not a whole number of {0}x{1} frames", layout.frame_width, layout.frame_height));
+        }
+        warnings.append(&mut check_animation(animation, &layout));
+
+        // Frame sizes are in pixels, so they grow with the texture
+        for key in ["width", "height"] {
+            if let Some(d) = get_dimension(animation, key)? {
+                animation.insert(String::from(key), Value::from(d * scale));
+            }
+        }
+    }
+
+    let bytes = serde_json::to_vec_pretty(&Value::Object(root))?;
+    return Ok((bytes, warnings));
+}
+
+fn check_animation(animation: &Map<String, Value>, layout: &FrameLayout) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if let Some(frametime) = animation.get("frametime") {
+        if frametime.as_u64().is_none_or(|t| t < 1) {
+            warnings.push(String::from("'frametime' should be a whole number of ticks, at least 1"));
+        }
+    }
+
+    if let Some(interpolate) = animation.get("interpolate") {
+        if !interpolate.is_boolean() {
+            warnings.push(String::from("'interpolate' should be true or false"));
+        }
+    }
+
+    if let Some(frames) = animation.get("frames") {
+        let frames = match frames.as_array() {
+            Some(f) => f,
+            None => {
+                warnings.push(String::from("'frames' should be a list"));
+                return warnings;
+            }
+        };
+
+        for frame in frames {
+            let (index, time) = match frame {
+                Value::Object(f) => (f.get("index").and_then(Value::as_u64), f.get("time")),
+                f => (f.as_u64(), None)
+            };
+            match index {
+                Some(i) if i < layout.frame_count() as u64 => (),
+                Some(i) => warnings.push(format!("frame {i} is out of range; the texture has {0} frames", layout.frame_count())),
+                None => warnings.push(String::from("'frames' contains an entry without a valid index"))
+            }
+            if let Some(time) = time {
+                if time.as_u64().is_none_or(|t| t < 1) {
+                    warnings.push(String::from("a frame 'time' should be a whole number of ticks, at least 1"));
+                }
+            }
+        }
+    }
+
+    return warnings;
+}
+
+fn get_dimension(animation: &Map<String, Value>, key: &str) -> Result<Option<u32>, ErrorKind> {
+    match animation.get(key) {
+        Some(v) => match v.as_u64() {
+            Some(d) => return Ok(Some(d as u32)),
+            None => return Err(ErrorKind::from(format!("animation frame '{key}' must be a whole number")))
+        },
+        None => return Ok(None)
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::BTreeSet, future::Future, path::{Path, PathBuf}, pin::Pin, process::ExitCode, sync::Arc, time::Duration};
+use notify::{event::{AccessKind, AccessMode}, Event, EventKind, RecursiveMode, Watcher};
+use tokio::{sync::{mpsc, Semaphore}, task::JoinSet, time::sleep};
+use walkdir::WalkDir;
+
+use crate::{fs_operations::{create_output_directory_structure, read_source_files, ResourceFilter}, resource_operations::{determine_resource_type, mcmeta_for_texture, process_resource, texture_for_mcmeta, ProcessingContext}};
+
+// Editors often save in several writes, so changes are gathered until the input has been quiet this long
+const SETTLE_TIME: Duration = Duration::from_millis(250);
+
+/// Keeps the output directory up to date with the input directory until Ctrl-C, reprocessing each file
+/// (and its texture or `.png.mcmeta`) as it's added or changed, and removing the outputs of deleted files.
+pub(crate) async fn watch_source(context: Arc<ProcessingContext>, filter: ResourceFilter, jobs: usize) -> ExitCode {
+    // Events carry absolute paths, so a relative input such as `-i pack` has to be resolved to match them
+    let root_path = match std::fs::canonicalize(context.source.path()) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Error: Unable to watch '{0}': {e}", context.source.path().display());
+            return ExitCode::FAILURE
+        }
+    };
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = match notify::recommended_watcher(move |event| { let _ = sender.send(event); }) {
+        Ok(w) => w,
+        Err(e) => {
+            println!("Error: Unable to watch '{0}': {e}", root_path.display());
+            return ExitCode::FAILURE
+        }
+    };
+    if let Err(e) = watcher.watch(&root_path, RecursiveMode::Recursive) {
+        println!("Error: Unable to watch '{0}': {e}", root_path.display());
+        return ExitCode::FAILURE
+    }
+
+    println!();
+    println!("Watching '{0}' for changes. Press Ctrl-C to stop.", root_path.display());
+
+    // One listener for the whole session, so Ctrl-C is caught while changes are settling or being processed too
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        let first = tokio::select! {
+            _ = &mut ctrl_c => break,
+            event = receiver.recv() => match event {
+                Some(e) => e,
+                None => break
+            }
+        };
+
+        let mut changes = Changes::default();
+        changes.add(first);
+        let mut interrupted = false;
+        loop {
+            tokio::select! {
+                _ = &mut ctrl_c => {
+                    interrupted = true;
+                    break;
+                },
+                event = receiver.recv() => match event {
+                    Some(e) => changes.add(e),
+                    None => break
+                },
+                _ = sleep(SETTLE_TIME) => break
+            }
+        }
+        if interrupted {
+            break;
+        }
+
+        let changed = if changes.rescan {
+            // The watcher lost track of some events, so everything is looked at again; the cache skips what's unchanged
+            match read_source_files(&context.source, &filter) {
+                Ok((r, _filtered_out)) => r,
+                Err(e) => {
+                    println!("Error: {e}");
+                    continue;
+                }
+            }
+        } else {
+            changes.relative_to(&root_path)
+        };
+
+        if apply_changes(&context, &filter, changed, jobs, ctrl_c.as_mut()).await {
+            break;
+        }
+    }
+
+    println!("Stopped watching.");
+    return ExitCode::SUCCESS;
+}
+
+/// The paths touched by a burst of file system events.
+#[derive(Default)]
+struct Changes {
+    paths: BTreeSet<PathBuf>,
+    rescan: bool,
+}
+
+impl Changes {
+    fn add(&mut self, event: notify::Result<Event>) {
+        let event = match event {
+            Ok(e) => e,
+            Err(_e) => {
+                self.rescan = true;
+                return;
+            }
+        };
+
+        if event.need_rescan() {
+            self.rescan = true;
+        }
+        // Reading a file (as processing it does) isn't a change; finishing writing one is
+        if let EventKind::Access(kind) = event.kind {
+            if kind != AccessKind::Close(AccessMode::Write) {
+                return;
+            }
+        }
+        self.paths.extend(event.paths);
+    }
+
+    /// The changed files within the pack. A new folder counts as all of the files in it, as they may have
+    /// arrived before the watcher started watching it.
+    fn relative_to(&self, root_path: &Path) -> Vec<PathBuf> {
+        let mut resources = BTreeSet::new();
+        for path in &self.paths {
+            if path.is_dir() {
+                for entry in WalkDir::new(path).into_iter().filter_map(Result::ok).filter(|e| e.file_type().is_file()) {
+                    if let Ok(r) = entry.path().strip_prefix(root_path) {
+                        resources.insert(r.to_owned());
+                    }
+                }
+            } else if let Ok(r) = path.strip_prefix(root_path) {
+                resources.insert(r.to_owned());
+            }
+        }
+        return resources.into_iter().collect();
+    }
+}
+
+/// Returns whether Ctrl-C was pressed, in which case what had been started is finished and the rest left.
+async fn apply_changes(context: &Arc<ProcessingContext>, filter: &ResourceFilter, changed: Vec<PathBuf>, jobs: usize,
+                       mut ctrl_c: Pin<&mut impl Future<Output = std::io::Result<()>>>) -> bool {
+    // A texture and its .png.mcmeta are processed together, so a new or deleted .mcmeta also redoes its texture
+    let mut resources = BTreeSet::new();
+    for r in changed {
+        match texture_for_mcmeta(&r) {
+            Some(t) => { resources.insert(t); },
+            None => { resources.insert(mcmeta_for_texture(&r)); }
+        }
+        resources.insert(r);
+    }
+
+    // A removed path may have been a whole folder, so what was made from it is looked up in the cache rather than filtered
+    let (updated, removed): (Vec<PathBuf>, Vec<PathBuf>) = resources.into_iter().partition(|r| context.source.contains(r));
+
+    for r in removed {
+        match context.cache.remove(&r) {
+            Ok(resources) => for resource in resources {
+                println!("Removed {0}", resource.display());
+            },
+            Err(e) => println!("Error: {e}")
+        }
+    }
+
+    let updated: Vec<PathBuf> = updated.into_iter()
+        .filter(|r| filter.allows(r) && !context.config.is_skipped(r))
+        .collect();
+
+    if let Err(e) = create_output_directory_structure(&context.sink, &updated).await {
+        println!("Error: {e}");
+        return false;
+    }
+    let mapped_resources = determine_resource_type(&updated, &context.config);
+
+    let permits = Arc::new(Semaphore::new(jobs));
+    let mut tasks = JoinSet::new();
+    let mut interrupted = false;
+    for (resource, resource_type) in mapped_resources {
+        let permit = tokio::select! {
+            biased;
+            _ = &mut ctrl_c => {
+                interrupted = true;
+                break;
+            },
+            p = permits.clone().acquire_owned() => match p {
+                Ok(p) => p,
+                Err(_e) => return false
+            }
+        };
+        let context = context.clone();
+        tasks.spawn_blocking(move || {
+            let result = process_resource(resource.clone(), resource_type, &context);
+            drop(permit);
+            match result {
+                // Only its texture or .mcmeta changed, and this turned out the same
+                Ok(o) if o.cached => (),
+                Ok(_o) => println!("Updated {0}", resource.display()),
+                Err(e) => println!("Error: {e}")
+            }
+        });
+    }
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c, if !interrupted => interrupted = true,
+            t = tasks.join_next() => if t.is_none() {
+                break;
+            }
+        }
+    }
+
+    if let Err(e) = context.cache.save(&context.source, &context.config) {
+        println!("Error: {e}");
+    }
+    return interrupted;
+}
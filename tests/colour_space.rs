@@ -0,0 +1,23 @@
+//! Which colour spaces change what the median picks.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use almostdefault::{upscale, ColourDistance, ColourSpace, MedianMode, Options, TextureKind};
+use common::noise_texture;
+
+fn upscaled(median_mode: MedianMode, colour_space: ColourSpace) -> image::RgbaImage {
+    let options = Options::builder().scale(4).median_mode(median_mode).colour_space(colour_space).build().unwrap();
+    return upscale(&noise_texture(16, 16, 3), &options, TextureKind::Item).unwrap();
+}
+
+#[test]
+fn classic_median_is_the_same_in_linear_light() {
+    assert!(upscaled(MedianMode::Classic, ColourSpace::Linear) == upscaled(MedianMode::Classic, ColourSpace::Srgb));
+}
+
+#[test]
+fn vector_median_depends_on_the_colour_space() {
+    let vector = MedianMode::Vector(ColourDistance::Rgb);
+    assert!(upscaled(vector, ColourSpace::Linear) != upscaled(vector, ColourSpace::Srgb));
+}
@@ -0,0 +1,19 @@
+//! Fixtures shared by the tests and benchmarks.
+#![allow(clippy::needless_return)]
+
+use image::{Rgba, RgbaImage};
+
+/// A texture of random colours, about one pixel in eight fully transparent. The same seed always gives the same texture.
+pub fn noise_texture(width: u32, height: u32, seed: u32) -> RgbaImage {
+    // xorshift, so runs are repeatable without pulling in a rand crate
+    let mut state = 0x9E37_79B9u32 ^ seed;
+    return RgbaImage::from_fn(width, height, |_, _| {
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 24) as u8
+        };
+        Rgba([next(), next(), next(), if next() < 32 { 0 } else { 255 }])
+    });
+}
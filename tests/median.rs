@@ -0,0 +1,30 @@
+//! The sliding-window median has to pick exactly what sorting every window would.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use almostdefault::bench::{median_filtered, median_filtered_reference};
+use common::noise_texture;
+use image::imageops::{self, FilterType};
+
+// The default windows for 4x, 8x, 16x and 32x
+const WINDOWS: [u32; 4] = [3, 5, 9, 17];
+const SIZES: [(u32, u32); 3] = [(16, 16), (16, 48), (24, 8)];
+
+#[test]
+fn sliding_median_matches_reference() {
+    for alpha_aware in [false, true] {
+        for window in WINDOWS {
+            for (width, height) in SIZES {
+                // Pixel-doubled like median_upscale does before filtering, plus the raw noise, where every window differs
+                let texture = noise_texture(width, height, window);
+                let doubled = imageops::resize(&texture, width * 2, height * 2, FilterType::Nearest);
+                for img in [texture, doubled] {
+                    let (w, h) = img.dimensions();
+                    assert!(median_filtered(&img, window, alpha_aware) == median_filtered_reference(&img, window, alpha_aware),
+                        "{w}x{h} image, {window}x{window} window, alpha_aware {alpha_aware}");
+                }
+            }
+        }
+    }
+}
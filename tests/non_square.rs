@@ -0,0 +1,39 @@
+//! Block textures aren't always square: tall animation strips, wide paintings and modded textures.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use almostdefault::{upscale, BlockBorder, Options, TextureKind};
+use common::noise_texture;
+use image::{imageops, RgbaImage};
+
+const SIZES: [(u32, u32); 2] = [(16, 32), (32, 16)];
+
+#[test]
+fn block_output_is_scaled_on_both_axes() {
+    for border in [BlockBorder::Wrap, BlockBorder::Mean] {
+        let options = Options::builder().scale(4).block_border(border).build().unwrap();
+        for (width, height) in SIZES {
+            let upscaled = upscale(&noise_texture(width, height, 1), &options, TextureKind::Block).unwrap();
+            assert_eq!(upscaled.dimensions(), (width * 4, height * 4), "{width}x{height} with {border:?} border");
+        }
+    }
+}
+
+#[test]
+fn block_pipeline_treats_rows_and_columns_alike() {
+    // Nothing in the pipeline favours one axis, so transposing the texture should just transpose the result
+    for border in [BlockBorder::Wrap, BlockBorder::Mean] {
+        let options = Options::builder().scale(4).block_border(border).build().unwrap();
+        for (width, height) in SIZES {
+            let texture = noise_texture(width, height, 2);
+            let upscaled = upscale(&texture, &options, TextureKind::Block).unwrap();
+            let transposed = upscale(&transpose(&texture), &options, TextureKind::Block).unwrap();
+            assert!(transpose(&upscaled) == transposed, "{width}x{height} with {border:?} border");
+        }
+    }
+}
+
+fn transpose(img: &RgbaImage) -> RgbaImage {
+    return imageops::flip_horizontal(&imageops::rotate90(img));
+}
@@ -0,0 +1,78 @@
+//! Runs the binary in `--watch` mode against a pack on disk.
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::{fs, io::{BufRead, BufReader}, path::{Path, PathBuf}, process::{Child, Command, Stdio}, sync::mpsc, thread, time::{Duration, Instant}};
+use common::noise_texture;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Stops the watcher and deletes the working folder, even if the test fails part way.
+struct Watcher {
+    child: Child,
+    folder: PathBuf,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.folder);
+    }
+}
+
+fn write_texture(path: &Path, seed: u32) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    noise_texture(16, 16, seed).save(path).unwrap();
+}
+
+fn wait_for(what: &str, condition: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < TIMEOUT, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn watch_follows_changes_to_a_relative_input() {
+    let folder = std::env::temp_dir().join(format!("almostdefault-watch-{0}", std::process::id()));
+    let _ = fs::remove_dir_all(&folder);
+    let textures = Path::new("assets/minecraft/textures");
+    write_texture(&folder.join("in").join(textures).join("block/stone.png"), 1);
+    write_texture(&folder.join("in").join(textures).join("item/stick.png"), 2);
+
+    let child = Command::new(env!("CARGO_BIN_EXE_almostdefault"))
+        .current_dir(&folder)
+        .args(["-i", "in", "-o", "out", "--watch", "-j", "1"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut watcher = Watcher { child, folder: folder.clone() };
+
+    let (sender, receiver) = mpsc::channel();
+    let stdout = BufReader::new(watcher.child.stdout.take().unwrap());
+    thread::spawn(move || {
+        for line in stdout.lines().map_while(Result::ok) {
+            if line.starts_with("Watching") {
+                let _ = sender.send(());
+            }
+        }
+    });
+    receiver.recv_timeout(TIMEOUT).expect("watch mode didn't start");
+
+    let output = folder.join("out").join(textures);
+    let stick = || fs::read(output.join("item/stick.png")).unwrap_or_default();
+    let old_stick = stick();
+
+    write_texture(&folder.join("in").join(textures).join("block/new.png"), 3);
+    write_texture(&folder.join("in").join(textures).join("item/stick.png"), 4);
+    wait_for("the new texture", || output.join("block/new.png").is_file());
+    wait_for("the changed texture", || stick() != old_stick);
+
+    // Moving a folder out of the input removes the outputs of everything that was in it
+    fs::rename(folder.join("in").join(textures).join("item"), folder.join("item")).unwrap();
+    wait_for("the moved folder's outputs to be removed", || !output.join("item/stick.png").exists());
+    assert!(output.join("block/stone.png").is_file());
+}